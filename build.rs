@@ -0,0 +1,14 @@
+use std::process::Command;
+
+// Provides git commit of the build as GIT_COMMIT env var
+fn main() {
+    let commit = Command::new("git").args(["rev-parse", "--short", "HEAD"]).output()
+                                    .ok()
+                                    .filter(|output| output.status.success())
+                                    .and_then(|output| String::from_utf8(output.stdout).ok())
+                                    .map(|commit| commit.trim().to_string())
+                                    .unwrap_or_else(|| "unknown".to_string());
+    println!("cargo:rustc-env=GIT_COMMIT={commit}");
+    println!("cargo:rerun-if-changed=.git/HEAD");
+    println!("cargo:rerun-if-changed=.git/refs/heads");
+}
@@ -0,0 +1,13 @@
+// Run with `cargo +nightly fuzz run sw_forecast_parser` from repository root
+#![no_main]
+
+use libfuzzer_sys::fuzz_target;
+
+use weather_provider::parsers::sw_forecast_parser;
+
+// parser must return error on any input, never panic
+fuzz_target!(|data: &[u8]| {
+    if let Ok(text) = std::str::from_utf8(data) {
+        let _ = sw_forecast_parser::parse_sw_forecast(text);
+    }
+});
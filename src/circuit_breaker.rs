@@ -0,0 +1,126 @@
+use std::time::{Duration, Instant};
+
+
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TBreakerState {
+    // requests pass, counting consecutive failures
+    Closed { failures: u32 },
+    // requests are blocked until cooldown ends
+    Open { until: Instant },
+    // single probe request is allowed
+    HalfOpen,
+}
+
+// Stops requests to failing source for cooldown period after `failure_threshold` consecutive failures
+#[derive(Debug, Clone)]
+pub struct TCircuitBreaker {
+    failure_threshold: u32,
+    cooldown: Duration,
+    state: TBreakerState,
+}
+
+impl TCircuitBreaker {
+    // zero threshold disables breaker
+    pub fn new(failure_threshold: u32, cooldown: Duration) -> Self {
+        Self { failure_threshold, cooldown, state: TBreakerState::Closed { failures: 0 } }
+    }
+
+    pub fn allow(&mut self, now: Instant) -> bool {
+        match self.state {
+            TBreakerState::Closed { .. } => true,
+            TBreakerState::Open { until } if now >= until => {
+                self.state = TBreakerState::HalfOpen;
+                true
+            },
+            TBreakerState::Open { .. } => false,
+            // probe is already in progress
+            TBreakerState::HalfOpen => false,
+        }
+    }
+
+    pub fn record_success(&mut self) {
+        self.state = TBreakerState::Closed { failures: 0 };
+    }
+
+    pub fn record_failure(&mut self, now: Instant) {
+        if self.failure_threshold == 0 {
+            return;
+        }
+        self.state = match self.state {
+            TBreakerState::Closed { failures } if failures + 1 < self.failure_threshold => {
+                TBreakerState::Closed { failures: failures + 1 }
+            },
+            _ => TBreakerState::Open { until: now + self.cooldown },
+        };
+    }
+
+    // probe is in progress, its outcome must be recorded to leave this state
+    pub fn is_half_open(&self) -> bool {
+        self.state == TBreakerState::HalfOpen
+    }
+
+    pub fn is_open(&self) -> bool {
+        matches!(self.state, TBreakerState::Open { .. })
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_breaker_opens_after_threshold() {
+        let now = Instant::now();
+        let mut breaker = TCircuitBreaker::new(3, Duration::from_secs(60));
+        for _ in 0..2 {
+            assert!(breaker.allow(now));
+            breaker.record_failure(now);
+        }
+        assert!(!breaker.is_open());
+        breaker.record_failure(now);
+        assert!(breaker.is_open());
+        assert!(!breaker.allow(now + Duration::from_secs(59)));
+    }
+
+    #[test]
+    fn test_breaker_half_open_probe() {
+        let now = Instant::now();
+        let mut breaker = TCircuitBreaker::new(1, Duration::from_secs(60));
+        breaker.record_failure(now);
+        let after_cooldown = now + Duration::from_secs(60);
+        // only single probe is allowed
+        assert!(breaker.allow(after_cooldown));
+        assert!(!breaker.allow(after_cooldown));
+        // failed probe opens circuit again
+        breaker.record_failure(after_cooldown);
+        assert!(!breaker.allow(after_cooldown + Duration::from_secs(1)));
+        // successful probe closes circuit
+        let after_cooldown = after_cooldown + Duration::from_secs(60);
+        assert!(breaker.allow(after_cooldown));
+        breaker.record_success();
+        assert!(breaker.allow(after_cooldown));
+        assert!(breaker.allow(after_cooldown));
+    }
+
+    #[test]
+    fn test_breaker_disabled() {
+        let now = Instant::now();
+        let mut breaker = TCircuitBreaker::new(0, Duration::from_secs(60));
+        for _ in 0..10 {
+            breaker.record_failure(now);
+            assert!(breaker.allow(now));
+        }
+    }
+
+    #[test]
+    fn test_breaker_success_resets_failures() {
+        let now = Instant::now();
+        let mut breaker = TCircuitBreaker::new(2, Duration::from_secs(60));
+        breaker.record_failure(now);
+        breaker.record_success();
+        breaker.record_failure(now);
+        assert!(breaker.allow(now));
+    }
+}
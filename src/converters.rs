@@ -1,21 +1,209 @@
 
-use chrono::NaiveDateTime;
-use serde::{Deserialize, Serialize};
+use chrono::{FixedOffset, Local, NaiveDateTime, TimeZone, Utc};
+use chrono_tz::Tz;
+use serde::{de::DeserializeOwned, Deserialize, Serialize};
+use std::collections::HashSet;
+use std::hash::Hash;
+use std::str::FromStr;
+use std::time::Duration;
 
 
+use crate::influx::{TFieldValue, TLineRecord};
 use crate::parsers::sw_forecast_parser::*;
+use crate::scales::*;
 
 
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PayloadFormat {
+    // whole converted records as JSON
+    #[default]
+    Json,
+    // only primary value of the most recent record, e.g. Kp
+    Scalar,
+}
+
+impl FromStr for PayloadFormat {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "json" => Ok(PayloadFormat::Json),
+            "scalar" => Ok(PayloadFormat::Scalar),
+            _ => Err(format!("unknown payload format '{s}', expected json or scalar")),
+        }
+    }
+}
+
+// Unit of published particle flux. NOAA publishes pfu, i.e. particles per cm2 per second per steradian.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum FluxUnit {
+    #[default]
+    Pfu,
+    // same values as pfu with SI-style label
+    PerCm2,
+    PerM2,
+}
+
+impl FluxUnit {
+    pub fn label(&self) -> &'static str {
+        match self {
+            FluxUnit::Pfu => "pfu",
+            FluxUnit::PerCm2 => "cm-2 s-1 sr-1",
+            FluxUnit::PerM2 => "m-2 s-1 sr-1",
+        }
+    }
+
+    // Multiplier of pfu value
+    fn factor(&self) -> f32 {
+        match self {
+            FluxUnit::Pfu | FluxUnit::PerCm2 => 1.0,
+            FluxUnit::PerM2 => 1e4,
+        }
+    }
+}
+
+impl FromStr for FluxUnit {
+    type Err = String;
+
+    // label with or without spaces, e.g. "cm-2 s-1 sr-1" or "cm-2s-1sr-1"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s.split_whitespace().collect::<String>().as_str() {
+            "pfu" => Ok(FluxUnit::Pfu),
+            "cm-2s-1sr-1" => Ok(FluxUnit::PerCm2),
+            "m-2s-1sr-1" => Ok(FluxUnit::PerM2),
+            _ => Err(format!("unknown flux unit '{s}', expected pfu, cm-2s-1sr-1 or m-2s-1sr-1")),
+        }
+    }
+}
+
+// Timezone of published timestamps, source data is in UTC
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum DisplayTimezone {
+    #[default]
+    Utc,
+    // system timezone, follows TZ env var (e.g. TZ=Europe/Berlin)
+    Local,
+    Fixed(FixedOffset),
+    // IANA name like "Europe/Berlin", offset follows DST
+    Named(Tz),
+}
+
+impl FromStr for DisplayTimezone {
+    type Err = String;
+
+    // "UTC", "local", fixed offset like "+03:00" or IANA name like "Europe/Berlin"
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "UTC" | "utc" => Ok(DisplayTimezone::Utc),
+            "local" => Ok(DisplayTimezone::Local),
+            _ => s.parse::<FixedOffset>().map(DisplayTimezone::Fixed)
+                  .or_else(|_| s.parse::<Tz>().map(DisplayTimezone::Named))
+                  .map_err(|_| format!("unknown timezone '{s}', expected UTC, local, offset like +03:00 \
+                                        or name like Europe/Berlin")),
+        }
+    }
+}
+
+// Format of published time tags
+const TIME_TAG_FORMAT: &str = "%H:%M %d-%m-%Y";
+
+// Topic suffix of companion JSON attributes message (HA `json_attributes_topic`)
+pub const ATTRIBUTES_TOPIC_SUFFIX: &str = "/attributes";
+
+// Errors of converting source data
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ConvertError {
+    // data isn't JSON/CSV of expected shape
+    #[error("deserilisation error: {0}")]
+    Deserialize(String),
+    // text or values don't match expected format, e.g. forecast tables or time tags
+    #[error("{0}")]
+    Parse(String),
+    // source has no usable records (yet)
+    #[error("{0}")]
+    Empty(&'static str),
+    // HTML maintenance or error page served instead of data, often with 200 status
+    #[error("expected {0} but got HTML page")]
+    Html(&'static str),
+    // converted data can't be serialized to payload, bug of converter
+    #[error("serilisation error: {0}")]
+    Serialize(String),
+}
+
+impl ConvertError {
+    // Error of source data, next fetches may fail the same way
+    pub fn is_source_fault(&self) -> bool {
+        !matches!(self, ConvertError::Serialize(_))
+    }
+}
+
+// Output of converter: (topic suffix, payload) pairs and typed records they are serialized from
+#[derive(Debug, Default, PartialEq)]
+pub struct Converted {
+    pub payloads: Vec<(String, String)>,
+    // records of payload of source topic for time series outputs, e.g. InfluxDB, forecasts have none
+    pub records: Vec<TLineRecord>,
+}
+
+// Per-source options of converters
+#[derive(Debug, Clone, Default)]
+pub struct ConvertOptions {
+    pub payload_format: PayloadFormat,
+    pub timezone: DisplayTimezone,
+    // number of decimals of published values, None - as received
+    pub precision: Option<u8>,
+    // data older than this is marked stale, None - no freshness fields in payloads
+    pub stale_after: Option<Duration>,
+    // records of every satellite are also published to own sub-topic, e.g. "/goes18"
+    pub split_by_satellite: bool,
+    pub forecast_topics: ForecastTopics,
+    // original NOAA timestamp is published as `raw_time_tag` next to converted `time_tag`
+    pub raw_time_tag: bool,
+    // proton flux is converted to this unit and labeled with `unit` field, None - pfu without label
+    pub flux_unit: Option<FluxUnit>,
+    // number of last records published by history converters, None - all records
+    pub history_records: Option<usize>,
+}
+
+// Topic name templates of forecast data points published as separate scalar payloads, None - not published.
+// Placeholders: {date} as "2024-05-01", {hour} - end of Kp interval, {grade} - storm grade, e.g. "s1" or "r3"
+#[derive(Debug, Clone, Default)]
+pub struct ForecastTopics {
+    pub kp: Option<String>,
+    pub srs: Option<String>,
+    pub rb: Option<String>,
+}
+
+// Age of data by its own timestamp
+#[derive(Serialize, Debug, Clone, PartialEq)]
+pub struct Freshness {
+    age_seconds: i64,
+    stale: bool,
+}
+
 #[derive(Serialize, Debug, Clone)]
 struct KpIndex {
     time_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_time_tag: Option<String>,
     kp: f32,
+    #[serde(flatten)]
+    freshness: Option<Freshness>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct KpInstAttributes {
+    time_tag: String,
+    g_scale: u8,
+    #[serde(flatten)]
+    freshness: Option<Freshness>,
 }
 
 #[derive(Deserialize, Debug, Clone)]
 struct KpInst {
     time_tag: String,
-    kp_index: f32,
+    // null during instrument gaps
+    kp_index: Option<f32>,
     #[serde(skip_deserializing)]
     _estimated_kp: f32,
     #[serde(skip_deserializing)]
@@ -25,136 +213,1208 @@ struct KpInst {
 #[derive(Deserialize, Debug, Clone)]
 struct ProtonFlux {
     time_tag: String,
-    #[serde(skip_deserializing)]
-    _satellite: u8,
-    flux: f32,
+    // GOES satellite number, primary and secondary data may be mixed
+    satellite: u8,
+    // null during instrument gaps
+    flux: Option<f32>,
+    energy: String,
+}
+
+// Same layout as proton flux, one item per energy
+#[derive(Deserialize, Debug, Clone)]
+struct ElectronFlux {
+    time_tag: String,
+    satellite: u8,
+    // null during instrument gaps
+    flux: Option<f32>,
+    energy: String,
+}
+
+// One item per wavelength band, flux in W/m2
+#[derive(Deserialize, Debug, Clone)]
+struct XrayFlux {
+    time_tag: String,
+    satellite: u8,
+    // null during instrument gaps
+    flux: Option<f64>,
     energy: String,
 }
 
+// Daily solar indices row of CelesTrak space weather CSV, see `SOLAR_INDICES_COLUMNS`
+#[derive(Deserialize, Debug, Clone)]
+struct SolarIndices {
+    date: String,
+    // OBS - observed, INT - interpolated, PRD/PRM - predicted
+    data_type: String,
+    f107: Option<f32>,
+    sunspot_number: Option<f32>,
+    ap: Option<f32>,
+}
+
+#[derive(Deserialize, Debug, Clone)]
+struct GoesMag {
+    time_tag: String,
+    #[serde(rename = "Hp")]
+    hp: Option<f32>,
+    #[serde(rename = "He")]
+    he: Option<f32>,
+    #[serde(rename = "Hn")]
+    hn: Option<f32>,
+}
+
+#[derive(Serialize, Debug, Clone)]
+struct GoesMagMQTT {
+    time_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_time_tag: Option<String>,
+    hp: f32,
+    he: f32,
+    hn: f32,
+}
+
 #[derive(Serialize, Debug, Clone)]
 struct ProtonFluxMQTT {
     time_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_time_tag: Option<String>,
+    satellite: u8,
     flux_gt10mev: f32,
     flux_gt50mev: f32,
     flux_gt100mev: f32,
     flux_gt500mev: f32,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    unit: Option<&'static str>,
+    // radiation storm level derived from >=10 MeV flux, 0 - below scale
+    s_scale: u8,
+    #[serde(flatten)]
+    freshness: Option<Freshness>,
 }
 
+#[derive(Serialize, Debug, Clone)]
+struct ElectronFluxMQTT {
+    time_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_time_tag: Option<String>,
+    satellite: u8,
+    flux_gt2mev: f32,
+    // "low", "moderate" or "high", see `electron_flux_risk`
+    risk: &'static str,
+    #[serde(flatten)]
+    freshness: Option<Freshness>,
+}
 
-pub fn converter_kp(raw_text: String) -> Result::<String, String> {
-    let raw_data: Vec<Vec<String>> = serde_json::from_str(raw_text.as_str())
-        .map_err(|e| format!("deserilisation error: {e}"))?;
+#[derive(Serialize, Debug, Clone)]
+struct XrayFluxMQTT {
+    time_tag: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    raw_time_tag: Option<String>,
+    satellite: u8,
+    // 0.1-0.8 nm band, W/m2
+    flux: f64,
+    // e.g. "M2.3"
+    flare_class: String,
+    // radio blackout level, 0 - below scale
+    r_scale: u8,
+    #[serde(flatten)]
+    freshness: Option<Freshness>,
+}
 
-    let num_elements = 7;   // FIXME
+#[derive(Serialize, Debug, Clone)]
+struct SolarIndicesMQTT {
+    date: String,
+    // 10.7 cm radio flux, sfu
+    f107: f32,
+    sunspot_number: f32,
+    // daily average of 3-hour ap
+    ap: f32,
+}
 
-    // skip header
-    let data_without_header = &raw_data[1..];
+// Maximum of forecast 3-hour Kp values per day
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct KpDailyMax {
+    date: String,
+    max_kp: f32,
+    // e.g. "G2", null - below scale
+    scale: Option<String>,
+}
+
+// First and last date of forecast tables, e.g. for "3-day forecast: May 01 - May 03" labels
+#[derive(Serialize, Debug, Clone, PartialEq)]
+struct ForecastWindow {
+    start: String,
+    end: String,
+}
+
+// Converters are split into parsing stage, that makes typed records from raw data, and serialization stage,
+// that makes MQTT payloads from the records.
+
+pub fn converter_kp(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (kp_data, records): (Vec<_>, _) = parse_kp_records(raw_text, 7, options)?.into_iter().unzip();   // FIXME
+    let payload = serialize_records(&kp_data, |record| record.kp, options.payload_format)?;
+    Ok(Converted { payloads: vec![("".to_string(), payload)], records })
+}
+
+// Publishes every historical record as separate message, e.g. to noaa_kp_history topic of backfill
+pub fn converter_kp_history(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (kp_data, records): (Vec<_>, _) =
+        parse_kp_records(raw_text, options.history_records.unwrap_or(usize::MAX), options)?.into_iter().unzip();
+    let payloads = kp_data.iter().map(|record| Ok(("".to_string(), to_json(record)?))).collect::<Result<_, _>>()?;
+    Ok(Converted { payloads, records })
+}
 
-    // determine initial index for slice
-    let start_index = if data_without_header.len() > num_elements {
-        data_without_header.len() - num_elements
-    } else {
-        0
+pub fn converter_kp_inst(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (current_kp, record) = parse_kp_inst(raw_text, options)?;
+    let payload = serialize_record(&current_kp, current_kp.kp, options.payload_format)?;
+    let attributes = KpInstAttributes {
+        time_tag: current_kp.time_tag.clone(),
+        g_scale: kp_to_g_scale(current_kp.kp),
+        freshness: current_kp.freshness,
     };
+    Ok(Converted {
+        payloads: vec![("".to_string(), payload), (ATTRIBUTES_TOPIC_SUFFIX.to_string(), to_json(&attributes)?)],
+        records: vec![record],
+    })
+}
+
+pub fn converter_flux(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let flux_records = parse_flux(raw_text, options)?;
+
+    let num_records = 2;    // FIXME: make custom struct with const field
+
+    // keep needed number of last records
+    let make_payload = |records: &[&ProtonFluxMQTT]| {
+        let records = &records[records.len().saturating_sub(num_records)..];
+        serialize_records(records, |record| record.flux_gt10mev, options.payload_format)
+    };
+
+    let mqtt_records: Vec<_> = flux_records.iter().map(|(record, _)| record).collect();
+    let mut payloads = vec![("".to_string(), make_payload(&mqtt_records)?)];
+    if options.split_by_satellite {
+        let mut satellites: Vec<u8> = mqtt_records.iter().map(|record| record.satellite).collect();
+        satellites.sort_unstable();
+        satellites.dedup();
+        for satellite in satellites {
+            let records: Vec<_> = mqtt_records.iter().copied().filter(|record| record.satellite == satellite)
+                                              .collect();
+            payloads.push((format!("/goes{satellite}"), make_payload(&records)?));
+        }
+    }
+    let records = flux_records[flux_records.len().saturating_sub(num_records)..].iter()
+        .map(|(_, record)| record.clone()).collect();
+    Ok(Converted { payloads, records })
+}
+
+pub fn converter_electron_flux(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (record, line_record) = parse_electron_flux(raw_text, options)?;
+    let payload = serialize_record(&record, record.flux_gt2mev, options.payload_format)?;
+    Ok(Converted { payloads: vec![("".to_string(), payload)], records: vec![line_record] })
+}
+
+pub fn converter_xray(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (record, line_record) = parse_xray(raw_text, options)?;
+    let payload = serialize_record(&record, record.flux as f32, options.payload_format)?;
+    Ok(Converted { payloads: vec![("".to_string(), payload)], records: vec![line_record] })
+}
+
+pub fn converter_solar_indices(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (record, line_record) = parse_solar_indices(raw_text, options)?;
+    let payload = serialize_record(&record, record.f107, options.payload_format)?;
+    Ok(Converted { payloads: vec![("".to_string(), payload)], records: vec![line_record] })
+}
+
+pub fn converter_goes_mag(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    let (record, line_record) = parse_goes_mag(raw_text, options)?;
+    let payload = serialize_record(&record, record.hp, options.payload_format)?;
+    Ok(Converted { payloads: vec![("".to_string(), payload)], records: vec![line_record] })
+}
+
+pub fn converter_sw_forecast(raw_text: String, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+    if raw_text.trim().is_empty() {
+        return Err(ConvertError::Empty("got no data"));
+    }
+
+    check_not_html(&raw_text, "text forecast")?;
+    let sw_data = parse_sw_forecast(raw_text.as_str()).map_err(ConvertError::Parse)?;
+
+    let mut payloads = vec![("".to_string(), to_json(&sw_data)?)];
+
+    // headline Kp values on dedicated topic
+    if let Some(kp_summary) = &sw_data.kp_summary {
+        payloads.push(("_kp_summary".to_string(), to_json(kp_summary)?));
+    }
+
+    if !sw_data.kp.is_empty() {
+        payloads.push(("_kp_daily_max".to_string(), to_json(&kp_daily_max(&sw_data.kp))?));
+    }
+
+    if let Some(window) = forecast_window(&sw_data) {
+        payloads.push(("_window".to_string(), to_json(&window)?));
+    }
 
-    // make slice with needed number of last elements
-    let required_data = &data_without_header[start_index..];
+    payloads.extend(forecast_point_payloads(&sw_data, &options.forecast_topics)?);
 
-    // move data to structs
-    let mut kp_data: Vec<KpIndex> = Vec::with_capacity(num_elements);
-    for item in required_data.iter() {
+    Ok(Converted { payloads, records: Vec::new() })
+}
+
+// One payload per forecast data point, e.g. Kp of May 01 18-21UT to "_kp_2024-05-01_21"
+fn forecast_point_payloads(sw_data: &SWForecast, topics: &ForecastTopics)
+    -> Result::<Vec<(String, String)>, ConvertError> {
+    let mut payloads = Vec::new();
+    if let Some(template) = &topics.kp {
+        for kp in &sw_data.kp {
+            let fields = [("date", forecast_date_token(&kp.date)), ("hour", kp.hour.to_string())];
+            payloads.push((format!("_{}", render_topic(template, &fields)), to_json(&kp.value)?));
+        }
+    }
+    for (template, records, letter) in [(&topics.srs, &sw_data.srs, 's'), (&topics.rb, &sw_data.rb, 'r')] {
+        let Some(template) = template else {
+            continue;
+        };
+        for record in records {
+            let grades = [record.s1, record.s2, record.s3, record.s4, record.s5];
+            for (index, probability) in grades.into_iter().enumerate() {
+                let fields = [("date", forecast_date_token(&record.date)), ("grade", format!("{letter}{}", index + 1))];
+                payloads.push((format!("_{}", render_topic(template, &fields)), to_json(&probability)?));
+            }
+        }
+    }
+    Ok(payloads)
+}
+
+fn render_topic(template: &str, fields: &[(&str, String)]) -> String {
+    fields.iter().fold(template.to_string(), |topic, (name, value)| topic.replace(&format!("{{{name}}}"), value))
+}
+
+// "May 01 2024" as "2024-05-01", unknown formats are made topic safe
+fn forecast_date_token(date: &str) -> String {
+    date_key(date).map(|date| date.format("%Y-%m-%d").to_string()).unwrap_or_else(|| topic_token(date))
+}
+
+// spaces, MQTT wildcards and level separators are replaced
+fn topic_token(value: &str) -> String {
+    value.chars().map(|c| if c.is_ascii_alphanumeric() || c == '-' { c } else { '_' }).collect()
+}
+
+// Parsing stage
+
+// HTML page is recognized by its leading tag, e.g. "<!DOCTYPE html>", data of sources never starts with '<'
+fn check_not_html(raw_text: &str, expected: &'static str) -> Result::<(), ConvertError> {
+    if raw_text.trim_start().starts_with('<') {
+        return Err(ConvertError::Html(expected));
+    }
+    Ok(())
+}
+
+fn from_json<T: DeserializeOwned>(raw_text: &str) -> Result::<T, ConvertError> {
+    check_not_html(raw_text, "JSON")?;
+    serde_json::from_str(raw_text).map_err(|e| ConvertError::Deserialize(e.to_string()))
+}
+
+// Returns last `num_elements` Kp records
+fn parse_kp_records(raw_text: String, num_elements: usize, options: &ConvertOptions)
+                    -> Result::<Vec<(KpIndex, TLineRecord)>, ConvertError> {
+    let raw_data: Vec<Vec<String>> = from_json(&raw_text)?;
+
+    // first row is a header, at least one data row is required
+    match raw_data.len() {
+        0 => return Err(ConvertError::Empty("got no data")),
+        1 => return Err(ConvertError::Empty("got only header without data")),
+        _ => (),
+    }
+
+    // skip header
+    let mut dated_data = Vec::with_capacity(raw_data.len() - 1);
+    for item in &raw_data[1..] {
         if let [time_tag, kp, ..] = &item[..] {
-            kp_data.push(KpIndex {
-                // add offset +3H to provide intervals's end timestamp insted of start timestamp
-                time_tag: convert_datetime(time_tag, "%Y-%m-%d %H:%M:%S%.3f", 3)?,
-                kp: kp.parse().unwrap_or(0.0),
-            });
+            // add offset +3H to provide intervals's end timestamp insted of start timestamp
+            let datetime = parse_datetime(time_tag, "%Y-%m-%d %H:%M:%S%.3f", 3)?;
+            dated_data.push((datetime, time_tag, kp));
         } else {
-            return Err(format!("error during parsing data"));
+            return Err(ConvertError::Parse("error during parsing data".to_string()));
         }
     }
+    sort_dedup_by_time(&mut dated_data, |(datetime, ..)| *datetime, |_| ());
 
-    serde_json::to_string(&kp_data).map_err(|e| format!("serilisation error: {e}"))
+    // move needed number of last elements to structs
+    let start_index = dated_data.len().saturating_sub(num_elements);
+    let kp_data = dated_data[start_index..].iter().map(|(datetime, time_tag, kp)| {
+        let kp = kp.parse().unwrap_or(0.0);
+        let record = KpIndex {
+            time_tag: format_datetime(*datetime, options.timezone),
+            raw_time_tag: raw_time_tag(time_tag, options),
+            kp: round_value(kp, options.precision),
+            freshness: freshness(*datetime, options),
+        };
+        let line_record = line_record(*datetime, vec![("kp", kp.into())], &record.freshness);
+        (record, line_record)
+    }).collect();
+
+    Ok(kp_data)
+}
+
+// Sorts records by time and keeps the last received one of records with equal time and id, e.g. satellite.
+// NOAA feeds occasionally repeat the last record or have slightly out-of-order trailing rows.
+fn sort_dedup_by_time<T, K: Eq + Hash>(records: &mut Vec<T>, time: impl Fn(&T) -> NaiveDateTime,
+                                       id: impl Fn(&T) -> K) {
+    let mut seen = HashSet::new();
+    records.reverse();
+    records.retain(|record| seen.insert((time(record), id(record))));
+    records.reverse();
+    // stable, records of the same time stay in order of data
+    records.sort_by_key(time);
 }
 
-pub fn converter_kp_inst(raw_text: String) -> Result::<String, String> {
-    let raw_data: Vec<KpInst> = serde_json::from_str(raw_text.as_str())
-        .map_err(|e| format!("deserilisation error: {e}"))?;
+// The most recent record with value
+fn parse_kp_inst(raw_text: String, options: &ConvertOptions) -> Result::<(KpIndex, TLineRecord), ConvertError> {
+    let raw_data: Vec<KpInst> = from_json(&raw_text)?;
 
-    // get only the most recent (last) element
-    let last_element = raw_data.last().ok_or_else(|| format!("got no data"))?;
+    let (last_element, kp_index) = raw_data.iter().rev()
+        .find_map(|item| Some((item, item.kp_index?)))
+        .ok_or(ConvertError::Empty("got no data"))?;
 
-    let current_kp = KpIndex {
-        time_tag: convert_datetime(&last_element.time_tag, "%Y-%m-%dT%H:%M:%S%Z", 0)?,
-        kp: last_element.kp_index,
+    let datetime = parse_datetime(&last_element.time_tag, "%Y-%m-%dT%H:%M:%S%Z", 0)?;
+    let record = KpIndex {
+        time_tag: format_datetime(datetime, options.timezone),
+        raw_time_tag: raw_time_tag(&last_element.time_tag, options),
+        kp: round_value(kp_index, options.precision),
+        freshness: freshness(datetime, options),
     };
+    let line_record = line_record(datetime, vec![("kp", kp_index.into())], &record.freshness);
+    Ok((record, line_record))
+}
 
-    serde_json::to_string(&current_kp).map_err(|e| format!("serilisation error: {e}"))
+// All complete records in order of data
+fn parse_flux(raw_text: String, options: &ConvertOptions)
+              -> Result::<Vec<(ProtonFluxMQTT, TLineRecord)>, ConvertError> {
+    let raw_data: Vec<ProtonFlux> = from_json(&raw_text)?;
+
+    if raw_data.is_empty() {
+        return Err(ConvertError::Empty("got no data"));
+    }
+
+    // records are grouped by time tag and satellite, one item per energy, groups with null values are skipped
+    let mut flux_records: Vec<(NaiveDateTime, ProtonFluxMQTT, TLineRecord)> = Vec::new();
+    let same_group = |item1: &ProtonFlux, item2: &ProtonFlux| {
+        item1.time_tag == item2.time_tag && item1.satellite == item2.satellite
+    };
+    let unit = options.flux_unit.unwrap_or_default();
+    for group in raw_data.chunk_by(same_group) {
+        // >=10, >=50, >=100 and >=500 MeV
+        let mut fluxes = [None; 4];
+        for item in group {
+            let Some(flux) = item.flux else {
+                continue;
+            };
+            let index = match item.energy.as_str() {
+                ">=10 MeV" => 0,
+                ">=50 MeV" => 1,
+                ">=100 MeV" => 2,
+                ">=500 MeV" => 3,
+                _ => continue,
+            };
+            fluxes[index] = Some(flux * unit.factor());
+        }
+        let [Some(flux_gt10mev), Some(flux_gt50mev), Some(flux_gt100mev), Some(flux_gt500mev)] = fluxes else {
+            continue;
+        };
+        // scale thresholds are in pfu
+        let s_scale = proton_flux_to_s_scale(flux_gt10mev / unit.factor());
+        let datetime = parse_datetime(group[0].time_tag.as_str(), "%Y-%m-%dT%H:%M:%S%Z", 0)?;
+        let mqtt_record = ProtonFluxMQTT {
+            time_tag: format_datetime(datetime, options.timezone),
+            raw_time_tag: raw_time_tag(&group[0].time_tag, options),
+            satellite: group[0].satellite,
+            flux_gt10mev: round_value(flux_gt10mev, options.precision),
+            flux_gt50mev: round_value(flux_gt50mev, options.precision),
+            flux_gt100mev: round_value(flux_gt100mev, options.precision),
+            flux_gt500mev: round_value(flux_gt500mev, options.precision),
+            unit: options.flux_unit.map(|unit| unit.label()),
+            s_scale,
+            freshness: freshness(datetime, options),
+        };
+        let fields = vec![("satellite", group[0].satellite.into()), ("flux_gt10mev", flux_gt10mev.into()),
+                          ("flux_gt50mev", flux_gt50mev.into()), ("flux_gt100mev", flux_gt100mev.into()),
+                          ("flux_gt500mev", flux_gt500mev.into()), ("s_scale", s_scale.into())];
+        let line_record = line_record(datetime, fields, &mqtt_record.freshness);
+        flux_records.push((datetime, mqtt_record, line_record));
+    }
+    sort_dedup_by_time(&mut flux_records, |(datetime, ..)| *datetime, |(_, record, _)| record.satellite);
+    Ok(flux_records.into_iter().map(|(_, record, line_record)| (record, line_record)).collect())
 }
 
-pub fn converter_flux(raw_text: String) -> Result::<String, String> {
-    let raw_data: Vec<ProtonFlux> = serde_json::from_str(raw_text.as_str())
-        .map_err(|e| format!("deserilisation error: {e}"))?;
+// Latest >=2 MeV electron flux, items of other energies and with nulls are skipped
+fn parse_electron_flux(raw_text: String, options: &ConvertOptions)
+                       -> Result::<(ElectronFluxMQTT, TLineRecord), ConvertError> {
+    let raw_data: Vec<ElectronFlux> = from_json(&raw_text)?;
 
-    let num_records = 2;    // FIXME: make custom struct with const field
+    // label is ">=2 MeV" in current feed, spacing differs between product versions
+    let is_gt2mev = |energy: &str| {
+        matches!(energy.split_whitespace().collect::<String>().as_str(), ">=2MeV" | ">2MeV" | ">=2.0MeV")
+    };
+    let (last_element, flux) = raw_data.iter().rev()
+        .filter(|item| is_gt2mev(&item.energy))
+        .find_map(|item| Some((item, item.flux?)))
+        .ok_or(ConvertError::Empty("got no data"))?;
 
-    // determine initial index for slice
-    let num_elements = num_records * 4;
-    let start_index = if raw_data.len() > num_elements {
-        raw_data.len() - num_elements
-    } else {
-        0
+    let datetime = parse_datetime(&last_element.time_tag, "%Y-%m-%dT%H:%M:%S%Z", 0)?;
+    let record = ElectronFluxMQTT {
+        time_tag: format_datetime(datetime, options.timezone),
+        raw_time_tag: raw_time_tag(&last_element.time_tag, options),
+        satellite: last_element.satellite,
+        flux_gt2mev: round_value(flux, options.precision),
+        risk: electron_flux_risk(flux),
+        freshness: freshness(datetime, options),
     };
+    let fields = vec![("satellite", record.satellite.into()), ("flux_gt2mev", flux.into()),
+                      ("risk", record.risk.into())];
+    let line_record = line_record(datetime, fields, &record.freshness);
+    Ok((record, line_record))
+}
 
-    // make slice with needed number of last elements
-    let required_data = &raw_data[start_index..];
-
-    // move data to structs
-    let mut flux_records: Vec<ProtonFluxMQTT> = Vec::with_capacity(num_records);
-    let mut mqtt_record = ProtonFluxMQTT {
-        time_tag: "".to_string(),
-        flux_gt10mev: 0.0,
-        flux_gt100mev: 0.0,
-        flux_gt50mev: 0.0,
-        flux_gt500mev: 0.0
+// Latest 0.1-0.8 nm X-ray flux, flare class and R-scale are derived from it. Flux isn't rounded,
+// as its values are far below any reasonable precision.
+fn parse_xray(raw_text: String, options: &ConvertOptions) -> Result::<(XrayFluxMQTT, TLineRecord), ConvertError> {
+    let raw_data: Vec<XrayFlux> = from_json(&raw_text)?;
+
+    let (last_element, flux) = raw_data.iter().rev()
+        .filter(|item| item.energy == "0.1-0.8nm")
+        .find_map(|item| Some((item, item.flux?)))
+        .ok_or(ConvertError::Empty("got no data"))?;
+
+    let datetime = parse_datetime(&last_element.time_tag, "%Y-%m-%dT%H:%M:%S%Z", 0)?;
+    let record = XrayFluxMQTT {
+        time_tag: format_datetime(datetime, options.timezone),
+        raw_time_tag: raw_time_tag(&last_element.time_tag, options),
+        satellite: last_element.satellite,
+        flux,
+        flare_class: xray_flux_to_flare_class(flux),
+        r_scale: xray_flux_to_r_scale(flux),
+        freshness: freshness(datetime, options),
+    };
+    let fields = vec![("satellite", record.satellite.into()), ("flux", flux.into()),
+                      ("flare_class", record.flare_class.as_str().into()), ("r_scale", record.r_scale.into())];
+    let line_record = line_record(datetime, fields, &record.freshness);
+    Ok((record, line_record))
+}
+
+// CelesTrak CSV column -> field of `SolarIndices`
+const SOLAR_INDICES_COLUMNS: [(&str, &str); 5] = [("DATE", "date"), ("F10.7_DATA_TYPE", "data_type"),
+                                                  ("F10.7_OBS", "f107"), ("ISN", "sunspot_number"),
+                                                  ("AP_AVG", "ap")];
+
+// Latest observed day, file goes on with predicted days without sunspot number and Kp
+fn parse_solar_indices(raw_text: String, options: &ConvertOptions)
+                       -> Result::<(SolarIndicesMQTT, TLineRecord), ConvertError> {
+    let raw_data: Vec<SolarIndices> = from_json(&csv_to_json(&raw_text, &SOLAR_INDICES_COLUMNS)?)?;
+
+    let (last_element, f107, sunspot_number, ap) = raw_data.iter().rev()
+        .filter(|item| item.data_type == "OBS")
+        .find_map(|item| Some((item, item.f107?, item.sunspot_number?, item.ap?)))
+        .ok_or(ConvertError::Empty("got no data"))?;
+
+    // daily values are dated at midnight UTC
+    let datetime = chrono::NaiveDate::parse_from_str(&last_element.date, "%Y-%m-%d")
+        .map_err(|e| ConvertError::Parse(format!("parsing date string error: {e}")))?.and_time(chrono::NaiveTime::MIN);
+    let record = SolarIndicesMQTT {
+        date: last_element.date.clone(),
+        f107: round_value(f107, options.precision),
+        sunspot_number,
+        ap,
     };
-    for item in required_data.iter() {
-        let flux_f32 = item.flux;
-        if item.energy == ">=10 MeV" {
-            mqtt_record.flux_gt10mev = flux_f32;
-        } else if item.energy == ">=100 MeV" {
-            mqtt_record.flux_gt100mev = flux_f32;
-        } else if item.energy == ">=50 MeV" {
-            mqtt_record.flux_gt50mev = flux_f32;
-        } else if item.energy == ">=500 MeV" {
-            mqtt_record.flux_gt500mev = flux_f32;
-            mqtt_record.time_tag = convert_datetime(item.time_tag.as_str(), "%Y-%m-%dT%H:%M:%S%Z", 0)?;
-            flux_records.push(mqtt_record.clone());
+    let fields = vec![("f107", f107.into()), ("sunspot_number", sunspot_number.into()), ("ap", ap.into())];
+    Ok((record, line_record(datetime, fields, &None)))
+}
+
+// Latest GOES magnetometer record with all components (nT), records during data gaps have nulls and are skipped
+fn parse_goes_mag(raw_text: String, options: &ConvertOptions) -> Result::<(GoesMagMQTT, TLineRecord), ConvertError> {
+    let raw_data: Vec<GoesMag> = from_json(&raw_text)?;
+
+    let (last_element, hp, he, hn) = raw_data.iter().rev()
+        .find_map(|item| Some((item, item.hp?, item.he?, item.hn?)))
+        .ok_or(ConvertError::Empty("got no data"))?;
+
+    let datetime = parse_datetime(&last_element.time_tag, "%Y-%m-%dT%H:%M:%S%Z", 0)?;
+    let record = GoesMagMQTT {
+        time_tag: format_datetime(datetime, options.timezone),
+        raw_time_tag: raw_time_tag(&last_element.time_tag, options),
+        hp: round_value(hp, options.precision),
+        he: round_value(he, options.precision),
+        hn: round_value(hn, options.precision),
+    };
+    let line_record = line_record(datetime, vec![("hp", hp.into()), ("he", he.into()), ("hn", hn.into())], &None);
+    Ok((record, line_record))
+}
+
+// Original timestamp of NOAA record, if enabled
+fn raw_time_tag(time_tag: &str, options: &ConvertOptions) -> Option<String> {
+    options.raw_time_tag.then(|| time_tag.to_string())
+}
+
+// Serialization stage
+
+fn to_json<T: Serialize + ?Sized>(value: &T) -> Result::<String, ConvertError> {
+    serde_json::to_string(value).map_err(|e| ConvertError::Serialize(e.to_string()))
+}
+
+// Whole record or only its primary value, e.g. Kp
+fn serialize_record<T: Serialize>(record: &T, primary: f32, payload_format: PayloadFormat)
+    -> Result::<String, ConvertError> {
+    match payload_format {
+        PayloadFormat::Json => to_json(record),
+        PayloadFormat::Scalar => to_json(&primary),
+    }
+}
+
+// All records or only primary value of the most recent (last) one
+fn serialize_records<T: Serialize>(records: &[T], primary: impl Fn(&T) -> f32, payload_format: PayloadFormat)
+                                   -> Result::<String, ConvertError> {
+    match payload_format {
+        PayloadFormat::Json => to_json(records),
+        PayloadFormat::Scalar => to_json(&primary(records.last().ok_or(ConvertError::Empty("got no data"))?)),
+    }
+}
+
+// Record of data at UTC `datetime` as parsed from NOAA time tag, with freshness fields if enabled
+fn line_record(datetime: NaiveDateTime, mut fields: Vec<(&'static str, TFieldValue)>, freshness: &Option<Freshness>)
+               -> TLineRecord {
+    if let Some(freshness) = freshness {
+        fields.extend([("age_seconds", freshness.age_seconds.into()), ("stale", freshness.stale.into())]);
+    }
+    TLineRecord { time: Some(datetime.and_utc()), fields }
+}
+
+// Dates of all tables, which may differ if NOAA issues them separately
+fn forecast_window(sw_data: &SWForecast) -> Option<ForecastWindow> {
+    let dates: Vec<&String> = sw_data.kp.iter().map(|kp| &kp.date)
+                                     .chain(sw_data.srs.iter().chain(&sw_data.rb).map(|record| &record.date))
+                                     .filter(|date| date_key(date).is_some())
+                                     .collect();
+    let start = dates.iter().min_by_key(|date| date_key(date))?;
+    let end = dates.iter().max_by_key(|date| date_key(date))?;
+    Some(ForecastWindow { start: start.to_string(), end: end.to_string() })
+}
+
+// Days in order of forecast
+fn kp_daily_max(kp: &[KPForecast]) -> Vec<KpDailyMax> {
+    let mut days: Vec<KpDailyMax> = Vec::new();
+    for item in kp {
+        match days.iter_mut().find(|day| day.date == item.date) {
+            Some(day) => day.max_kp = day.max_kp.max(item.value),
+            None => days.push(KpDailyMax { date: item.date.clone(), max_kp: item.value, scale: None }),
         }
     }
+    for day in &mut days {
+        day.scale = scale_name('G', kp_to_g_scale(day.max_kp));
+    }
+    days
+}
 
-    serde_json::to_string(&flux_records).map_err(|e| format!("serilisation error: {e}"))
+// Converts CSV text with header row to JSON array of objects. `columns` maps CSV column name to JSON field name,
+// numeric values become JSON numbers, empty ones null, other columns are dropped. Lines starting with '#' are comments.
+pub fn csv_to_json(raw_text: &str, columns: &[(&str, &str)]) -> Result::<String, ConvertError> {
+    check_not_html(raw_text, "CSV")?;
+    let mut reader = csv::ReaderBuilder::new()
+        .comment(Some(b'#'))
+        .trim(csv::Trim::All)
+        .flexible(true)
+        .from_reader(raw_text.as_bytes());
+    let csv_error = |e: csv::Error| ConvertError::Deserialize(format!("CSV: {e}"));
+    let header = reader.headers().map_err(csv_error)?.clone();
+    if header.is_empty() {
+        return Err(ConvertError::Empty("got no data"));
+    }
+    let column_not_found = |column: &str| ConvertError::Deserialize(format!("CSV column '{column}' not found"));
+    let indexes = columns.iter()
+        .map(|(column, field)| header.iter().position(|name| name == *column)
+                                     .map(|index| (index, *field))
+                                     .ok_or_else(|| column_not_found(column)))
+        .collect::<Result<Vec<_>, _>>()?;
+
+    let records = reader.records().map(|row| {
+        let row = row.map_err(csv_error)?;
+        let record = indexes.iter().map(|(index, field)| {
+            let value = match row.get(*index).unwrap_or_default() {
+                "" => serde_json::Value::Null,
+                value => match value.parse::<f64>() {
+                    Ok(number) => serde_json::json!(number),
+                    Err(_) => serde_json::json!(value),
+                },
+            };
+            (field.to_string(), value)
+        }).collect();
+        Ok(serde_json::Value::Object(record))
+    }).collect::<Result<Vec<_>, ConvertError>>()?;
+    if records.is_empty() {
+        return Err(ConvertError::Empty("got only header without data"));
+    }
+    serde_json::to_string(&records).map_err(|e| ConvertError::Serialize(e.to_string()))
 }
 
-pub fn converter_sw_forecast(raw_text: String) -> Result::<String, String> {
-    let sw_data = parse_sw_forecast(raw_text.as_str())?;
+// Finds primary numeric value in converted payload: the payload itself for scalar, `field` of object
+// or of the most recent (last) object in array
+pub fn primary_value_mut<'a>(value: &'a mut serde_json::Value, field: &str) -> Option<&'a mut serde_json::Value> {
+    match value {
+        serde_json::Value::Number(_) => Some(value),
+        serde_json::Value::Object(map) => map.get_mut(field).filter(|v| v.is_number()),
+        serde_json::Value::Array(items) => items.last_mut().and_then(|item| primary_value_mut(item, field)),
+        _ => None,
+    }
+}
 
-    // for kp_data in &sw_data.kp {
-    //     println!("Date: {}, Time end: {}, Kp: {}", kp_data.date, kp_data.hour, kp_data.value);
-    // }
-    // for srs_data in &sw_data.srs {
-    //     println!("Date: {}, S1: {}, S2: {}, S3: {}, S4: {}, S5: {}", srs_data.date, srs_data.s1, srs_data.s2, srs_data.s3, srs_data.s4, srs_data.s5);
-    // }
-    // for rb_data in &sw_data.rb {
-    //     println!("Date: {}, R1: {}, R2: {}, R3: {}, R4: {}, R5: {}", rb_data.date, rb_data.s1, rb_data.s2, rb_data.s3, rb_data.s4, rb_data.s5);
-    // }
+pub fn round_value(value: f32, precision: Option<u8>) -> f32 {
+    match precision {
+        Some(decimals) => {
+            let factor = 10f32.powi(decimals.into());
+            (value * factor).round() / factor
+        },
+        None => value,
+    }
+}
+
+// Parses UTC datetime and formats it in display timezone
+pub fn convert_datetime(input: &str, in_format: &str, offset_hours: i64, timezone: DisplayTimezone)
+                        -> Result::<String, ConvertError> {
+    Ok(format_datetime(parse_datetime(input, in_format, offset_hours)?, timezone))
+}
+
+fn parse_datetime(input: &str, in_format: &str, offset_hours: i64) -> Result::<NaiveDateTime, ConvertError> {
+    let datetime = NaiveDateTime::parse_from_str(input, in_format)
+        .map_err(|e| ConvertError::Parse(format!("parsing datetime string error: {e}")))?;
+    Ok(datetime + chrono::Duration::hours(offset_hours))
+}
 
-    serde_json::to_string(&sw_data).map_err(|e| format!("serilisation error: {e}"))
+fn format_datetime(datetime: NaiveDateTime, timezone: DisplayTimezone) -> String {
+    let datetime = Utc.from_utc_datetime(&datetime);
+    match timezone {
+        DisplayTimezone::Utc => datetime.format(TIME_TAG_FORMAT).to_string(),
+        DisplayTimezone::Local => datetime.with_timezone(&Local).format(TIME_TAG_FORMAT).to_string(),
+        DisplayTimezone::Fixed(offset) => datetime.with_timezone(&offset).format(TIME_TAG_FORMAT).to_string(),
+        DisplayTimezone::Named(tz) => datetime.with_timezone(&tz).format(TIME_TAG_FORMAT).to_string(),
+    }
 }
 
-pub fn convert_datetime(input: &str, in_format: &str, offset_hours: i64) -> Result::<String, String> {
-    let mut datetime = NaiveDateTime::parse_from_str(input, in_format)
-        .map_err(|e| format!("parsing datetime string error: {e}"))?;
-    datetime += chrono::Duration::hours(offset_hours);
-    Ok(datetime.format("%H:%M %d-%m-%Y").to_string())
+fn freshness(datetime: NaiveDateTime, options: &ConvertOptions) -> Option<Freshness> {
+    let stale_after = options.stale_after?;
+    Some(freshness_at(datetime, Utc::now().naive_utc(), stale_after))
+}
+
+fn freshness_at(datetime: NaiveDateTime, now: NaiveDateTime, stale_after: Duration) -> Freshness {
+    let age_seconds = (now - datetime).num_seconds();
+    Freshness { age_seconds, stale: age_seconds > stale_after.as_secs() as i64 }
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::influx::to_line_protocol;
+
+    const KP_DATA: &str = include_str!("../tests/fixtures/noaa-planetary-k-index.json");
+    const KP_DATA_SHORT: &str = include_str!("../tests/fixtures/noaa-planetary-k-index-short.json");
+    const KP_DATA_DUPLICATES: &str = include_str!("../tests/fixtures/noaa-planetary-k-index-duplicates.json");
+    const KP_INST_DATA: &str = include_str!("../tests/fixtures/planetary_k_index_1m.json");
+    const FLUX_DATA: &str = include_str!("../tests/fixtures/integral-protons-plot-6-hour.json");
+    const FLUX_DATA_SHORT: &str = include_str!("../tests/fixtures/integral-protons-plot-6-hour-short.json");
+    const FLUX_DATA_DUPLICATES: &str = include_str!("../tests/fixtures/integral-protons-plot-6-hour-duplicates.json");
+    const FLUX_DATA_NULLS: &str = include_str!("../tests/fixtures/integral-protons-plot-6-hour-nulls.json");
+    const KP_INST_DATA_NULLS: &str = include_str!("../tests/fixtures/planetary_k_index_1m-nulls.json");
+    const ELECTRON_FLUX_DATA: &str = include_str!("../tests/fixtures/integral-electrons-plot-3-day-short.json");
+    const XRAY_DATA: &str = include_str!("../tests/fixtures/xrays-6-hour-short.json");
+    const SOLAR_INDICES_DATA: &str = include_str!("../tests/fixtures/SW-Last5Years-short.csv");
+    const GOES_MAG_DATA: &str = include_str!("../tests/fixtures/magnetometers-1-day-short.json");
+    const SW_FORECAST_DATA: &str = include_str!("../tests/fixtures/3-day-forecast.txt");
+
+    #[test]
+    fn test_converter_kp() {
+        let result = converter_kp(KP_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"09:00 30-04-2024\",\"kp\":3.0},{\"time_tag\":\"12:00 30-04-2024\",\"kp\":2.67},\
+                     {\"time_tag\":\"15:00 30-04-2024\",\"kp\":3.33},{\"time_tag\":\"18:00 30-04-2024\",\"kp\":4.0},\
+                     {\"time_tag\":\"21:00 30-04-2024\",\"kp\":4.67},{\"time_tag\":\"00:00 01-05-2024\",\"kp\":3.67},\
+                     {\"time_tag\":\"03:00 01-05-2024\",\"kp\":3.0}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_converter_kp_short_data() {
+        let result = converter_kp(KP_DATA_SHORT.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"03:00 01-05-2024\",\"kp\":3.0},{\"time_tag\":\"06:00 01-05-2024\",\"kp\":2.33}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_converter_kp_scalar() {
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_kp(KP_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "3.0".to_string())]);
+    }
+
+    #[test]
+    fn test_converter_kp_history() {
+        let result = converter_kp_history(KP_DATA_SHORT.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        assert_eq!(result, vec![
+            ("".to_string(), "{\"time_tag\":\"03:00 01-05-2024\",\"kp\":3.0}".to_string()),
+            ("".to_string(), "{\"time_tag\":\"06:00 01-05-2024\",\"kp\":2.33}".to_string()),
+        ]);
+        let options = ConvertOptions { history_records: Some(1), ..Default::default() };
+        let result = converter_kp_history(KP_DATA_SHORT.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "{\"time_tag\":\"06:00 01-05-2024\",\"kp\":2.33}".to_string())]);
+    }
+
+    #[test]
+    fn test_converter_kp_duplicates() {
+        // repeated tail record and out-of-order row are published once in order of time
+        let result = converter_kp_history(KP_DATA_DUPLICATES.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        assert_eq!(result, vec![
+            ("".to_string(), "{\"time_tag\":\"03:00 01-05-2024\",\"kp\":3.0}".to_string()),
+            ("".to_string(), "{\"time_tag\":\"06:00 01-05-2024\",\"kp\":2.33}".to_string()),
+            ("".to_string(), "{\"time_tag\":\"09:00 01-05-2024\",\"kp\":2.67}".to_string()),
+        ]);
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        assert_eq!(converter_kp(KP_DATA_DUPLICATES.to_string(), &options).unwrap().payloads[0].1, "2.67");
+    }
+
+    #[test]
+    fn test_sort_dedup_by_time() {
+        let time = |minute: u32| NaiveDateTime::parse_from_str(&format!("2024-05-01 00:{minute:02}"), "%Y-%m-%d %H:%M")
+                                                .unwrap();
+        // (minute, satellite, value)
+        let mut records = vec![(1, 18, 'a'), (3, 18, 'b'), (3, 16, 'c'), (2, 18, 'd'), (3, 18, 'e')];
+        sort_dedup_by_time(&mut records, |(minute, ..)| time(*minute), |(_, satellite, _)| *satellite);
+        assert_eq!(records, [(1, 18, 'a'), (2, 18, 'd'), (3, 16, 'c'), (3, 18, 'e')]);
+    }
+
+    #[test]
+    fn test_converter_kp_empty_data() {
+        assert_eq!(converter_kp("[]".to_string(), &ConvertOptions::default()), Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_kp_header_only() {
+        let header_only = r#"[["time_tag","Kp","a_running","station_count"]]"#;
+        assert_eq!(converter_kp(header_only.to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got only header without data")));
+    }
+
+    #[test]
+    fn test_converter_kp_inst() {
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        assert_eq!(result, vec![
+            ("".to_string(), "{\"time_tag\":\"00:29 01-05-2024\",\"kp\":4.0}".to_string()),
+            ("/attributes".to_string(), "{\"time_tag\":\"00:29 01-05-2024\",\"g_scale\":0}".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_converter_kp_inst_scalar() {
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![
+            ("".to_string(), "4.0".to_string()),
+            ("/attributes".to_string(), "{\"time_tag\":\"00:29 01-05-2024\",\"g_scale\":0}".to_string()),
+        ]);
+    }
+
+    #[test]
+    fn test_converter_kp_inst_empty_data() {
+        assert_eq!(converter_kp_inst("[]".to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_flux() {
+        let result = converter_flux(FLUX_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"00:05 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":0.33,\
+                     \"flux_gt50mev\":0.13,\"flux_gt100mev\":0.09,\"flux_gt500mev\":0.03,\"s_scale\":0},\
+                     {\"time_tag\":\"00:10 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":0.35,\
+                     \"flux_gt50mev\":0.14,\"flux_gt100mev\":0.1,\"flux_gt500mev\":0.04,\"s_scale\":0}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_parse_flux() {
+        // parsing stage keeps all complete records, serialization takes the last ones
+        let records = parse_flux(FLUX_DATA.to_string(), &ConvertOptions::default()).unwrap();
+        assert!(records.len() > 2);
+        let (last, _) = records.last().unwrap();
+        assert_eq!((last.time_tag.as_str(), last.satellite, last.flux_gt10mev), ("00:10 01-05-2024", 18, 0.35));
+    }
+
+    #[test]
+    fn test_parse_flux_duplicates() {
+        // trailing older record repeats one already received
+        let records = parse_flux(FLUX_DATA_DUPLICATES.to_string(), &ConvertOptions::default()).unwrap();
+        let time_tags: Vec<_> = records.iter().map(|(record, _)| record.time_tag.as_str()).collect();
+        assert_eq!(time_tags, ["00:05 01-05-2024", "00:10 01-05-2024"]);
+        assert_eq!(records[1].0.flux_gt10mev, 0.35);
+    }
+
+    #[test]
+    fn test_serialize_records() {
+        let records = [1.5_f32, 2.5];
+        assert_eq!(serialize_records(&records, |value| *value, PayloadFormat::Json), Ok("[1.5,2.5]".to_string()));
+        assert_eq!(serialize_records(&records, |value| *value, PayloadFormat::Scalar), Ok("2.5".to_string()));
+        assert_eq!(serialize_records::<f32>(&[], |value| *value, PayloadFormat::Scalar),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_flux_short_data() {
+        let result = converter_flux(FLUX_DATA_SHORT.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"00:10 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":0.35,\
+                     \"flux_gt50mev\":0.14,\"flux_gt100mev\":0.1,\"flux_gt500mev\":0.04,\"s_scale\":0}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_converter_flux_s_scale() {
+        let storm_data = FLUX_DATA_SHORT.replace("0.35", "150.0");
+        let result = converter_flux(storm_data, &ConvertOptions::default()).unwrap().payloads;
+        assert!(result[0].1.ends_with("\"s_scale\":2}]"));
+    }
+
+    #[test]
+    fn test_converter_flux_nulls() {
+        let result = converter_flux(FLUX_DATA_NULLS.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"00:05 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":0.33,\
+                     \"flux_gt50mev\":0.13,\"flux_gt100mev\":0.09,\"flux_gt500mev\":0.03,\"s_scale\":0},\
+                     {\"time_tag\":\"00:15 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":0.36,\
+                     \"flux_gt50mev\":0.15,\"flux_gt100mev\":0.1,\"flux_gt500mev\":0.04,\"s_scale\":0}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_converter_kp_inst_nulls() {
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_kp_inst(KP_INST_DATA_NULLS.to_string(), &options).unwrap().payloads;
+        assert_eq!(result[0], ("".to_string(), "3.0".to_string()));
+        assert_eq!(result[1].1, "{\"time_tag\":\"00:27 01-05-2024\",\"g_scale\":0}");
+    }
+
+    #[test]
+    fn test_converter_flux_scalar() {
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_flux(FLUX_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "0.35".to_string())]);
+    }
+
+    #[test]
+    fn test_converter_flux_split_by_satellite() {
+        // same time tag from primary and secondary satellites
+        let secondary = FLUX_DATA_SHORT.replace("\"satellite\":18", "\"satellite\":16").replace("0.35", "0.5");
+        let mixed = FLUX_DATA_SHORT.trim_end().trim_end_matches(']').to_string() + "," + &secondary[1..];
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, split_by_satellite: true,
+                                       ..Default::default() };
+        let result = converter_flux(mixed, &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "0.5".to_string()),
+                                ("/goes16".to_string(), "0.5".to_string()),
+                                ("/goes18".to_string(), "0.35".to_string())]);
+    }
+
+    #[test]
+    fn test_converter_flux_unit() {
+        let options = ConvertOptions { flux_unit: Some(FluxUnit::PerM2), ..Default::default() };
+        let result = converter_flux(FLUX_DATA_SHORT.to_string(), &options).unwrap().payloads;
+        let expected = "[{\"time_tag\":\"00:10 01-05-2024\",\"satellite\":18,\"flux_gt10mev\":3500.0,\
+                     \"flux_gt50mev\":1400.0,\"flux_gt100mev\":1000.0,\"flux_gt500mev\":400.0,\
+                     \"unit\":\"m-2 s-1 sr-1\",\"s_scale\":0}]";
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+
+        // S1 starts at 10 pfu whatever the unit is
+        let storm_data = FLUX_DATA_SHORT.replace("0.35", "15.0");
+        let records = parse_flux(storm_data, &options).unwrap();
+        assert_eq!((records[0].0.flux_gt10mev, records[0].0.s_scale), (150000.0, 1));
+        let options = ConvertOptions { flux_unit: Some(FluxUnit::Pfu), ..Default::default() };
+        let records = parse_flux(FLUX_DATA_SHORT.to_string(), &options).unwrap();
+        assert_eq!((records[0].0.flux_gt10mev, records[0].0.unit), (0.35, Some("pfu")));
+    }
+
+    #[test]
+    fn test_parse_flux_unit() {
+        assert_eq!("pfu".parse(), Ok(FluxUnit::Pfu));
+        assert_eq!("cm-2 s-1 sr-1".parse(), Ok(FluxUnit::PerCm2));
+        assert_eq!("m-2s-1sr-1".parse(), Ok(FluxUnit::PerM2));
+        assert!("W/m2".parse::<FluxUnit>().is_err());
+    }
+
+    #[test]
+    fn test_converter_flux_empty_data() {
+        assert_eq!(converter_flux("[]".to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_freshness_at() {
+        let datetime = parse_datetime("2024-05-01T00:29:00Z", "%Y-%m-%dT%H:%M:%S%Z", 0).unwrap();
+        let now = datetime + chrono::Duration::minutes(10);
+        assert_eq!(freshness_at(datetime, now, Duration::from_secs(900)), Freshness { age_seconds: 600, stale: false });
+        assert_eq!(freshness_at(datetime, now, Duration::from_secs(300)), Freshness { age_seconds: 600, stale: true });
+    }
+
+    #[test]
+    fn test_converter_kp_inst_stale() {
+        let options = ConvertOptions { stale_after: Some(Duration::from_secs(900)), ..Default::default() };
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &options).unwrap().payloads;
+        // fixture is from 2024
+        assert!(result[0].1.ends_with("\"stale\":true}"));
+        assert!(result[1].1.contains("\"age_seconds\":"));
+        assert!(result[1].1.ends_with("\"stale\":true}"));
+    }
+
+    #[test]
+    fn test_round_value() {
+        assert_eq!(round_value(3.671, None), 3.671);
+        assert_eq!(round_value(3.671, Some(2)), 3.67);
+        assert_eq!(round_value(0.346, Some(1)), 0.3);
+        assert_eq!(round_value(2.5, Some(0)), 3.0);
+    }
+
+    #[test]
+    fn test_converter_flux_precision() {
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, precision: Some(1),
+                                       ..Default::default() };
+        let result = converter_flux(FLUX_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "0.4".to_string())]);
+    }
+
+    #[test]
+    fn test_display_timezone() {
+        assert_eq!("UTC".parse(), Ok(DisplayTimezone::Utc));
+        assert_eq!("local".parse(), Ok(DisplayTimezone::Local));
+        assert!("Europe/Nowhere".parse::<DisplayTimezone>().is_err());
+        let timezone: DisplayTimezone = "+03:00".parse().unwrap();
+        let result = convert_datetime("2024-04-30 21:00:00.000", "%Y-%m-%d %H:%M:%S%.3f", 3, timezone);
+        assert_eq!(result, Ok("03:00 01-05-2024".to_string()));
+        let timezone: DisplayTimezone = "-05:30".parse().unwrap();
+        let result = convert_datetime("2024-05-01T00:29:00Z", "%Y-%m-%dT%H:%M:%S%Z", 0, timezone);
+        assert_eq!(result, Ok("18:59 30-04-2024".to_string()));
+        // CET in winter, CEST in summer
+        let timezone: DisplayTimezone = "Europe/Berlin".parse().unwrap();
+        let result = convert_datetime("2024-01-15T12:00:00Z", "%Y-%m-%dT%H:%M:%S%Z", 0, timezone);
+        assert_eq!(result, Ok("13:00 15-01-2024".to_string()));
+        let result = convert_datetime("2024-05-01T00:29:00Z", "%Y-%m-%dT%H:%M:%S%Z", 0, timezone);
+        assert_eq!(result, Ok("02:29 01-05-2024".to_string()));
+    }
+
+    #[test]
+    fn test_converter_kp_inst_timezone() {
+        let options = ConvertOptions { timezone: "+02:00".parse().unwrap(), ..Default::default() };
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result[0].1, "{\"time_tag\":\"02:29 01-05-2024\",\"kp\":4.0}");
+    }
+
+    #[test]
+    fn test_raw_time_tag() {
+        let options = ConvertOptions { timezone: "+02:00".parse().unwrap(), raw_time_tag: true, ..Default::default() };
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result[0].1, r#"{"time_tag":"02:29 01-05-2024","raw_time_tag":"2024-05-01T00:29:00","kp":4.0}"#);
+        let result = converter_kp(KP_DATA.to_string(), &options).unwrap().payloads;
+        // Kp time tag is end of interval, raw one is its start
+        assert!(result[0].1.starts_with(r#"[{"time_tag":"11:00 30-04-2024","raw_time_tag":"2024-04-30 06:00:00.000""#));
+    }
+
+    #[test]
+    fn test_csv_to_json() {
+        let csv = "time_tag,satellite,flux\n2024-05-01 00:00,16,0.33\n2024-05-01 00:05,16,n/a\n";
+        let result = csv_to_json(csv, &[("time_tag", "time_tag"), ("flux", "flux_gt10mev")]);
+        // fields are ordered by name
+        assert_eq!(result, Ok("[{\"flux_gt10mev\":0.33,\"time_tag\":\"2024-05-01 00:00\"},\
+                               {\"flux_gt10mev\":\"n/a\",\"time_tag\":\"2024-05-01 00:05\"}]".to_string()));
+        assert_eq!(csv_to_json(csv, &[("energy", "energy")]),
+                   Err(ConvertError::Deserialize("CSV column 'energy' not found".to_string())));
+        assert_eq!(csv_to_json("time_tag,flux\n", &[]), Err(ConvertError::Empty("got only header without data")));
+        assert_eq!(csv_to_json("", &[]), Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_csv_to_json_quoted() {
+        // quoted commas, line breaks and quotes, comment lines, CRLF and empty values
+        let csv = "# comment\r\nclass, note ,flux\r\n\"C1.5, long\",\"line\nbreak \"\"x\"\"\",\r\n";
+        let result = csv_to_json(csv, &[("class", "class"), ("note", "note"), ("flux", "flux")]);
+        assert_eq!(result, Ok(r#"[{"class":"C1.5, long","flux":null,"note":"line\nbreak \"x\""}]"#.to_string()));
+    }
+
+    #[test]
+    fn test_converter_electron_flux() {
+        let result = converter_electron_flux(ELECTRON_FLUX_DATA.to_string(), &Default::default()).unwrap().payloads;
+        // trailing record with null and >=0.8 MeV items are skipped, ">= 2 MeV" label is matched
+        let expected = r#"{"time_tag":"00:05 01-05-2024","satellite":18,"flux_gt2mev":1250.75,"risk":"moderate"}"#;
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_electron_flux(ELECTRON_FLUX_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(), "1250.75".to_string())]);
+
+        let only_low_energy = r#"[{"time_tag": "2024-05-01T00:00:00Z", "satellite": 18, "flux": 1.2e5,
+                                   "energy": ">=0.8 MeV"}]"#;
+        assert_eq!(converter_electron_flux(only_low_energy.to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_solar_indices() {
+        let result = converter_solar_indices(SOLAR_INDICES_DATA.to_string(), &Default::default()).unwrap().payloads;
+        assert_eq!(result, vec![("".to_string(),
+                                 r#"{"date":"2024-05-01","f107":175.5,"sunspot_number":161.0,"ap":7.0}"#.to_string())]);
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, ..Default::default() };
+        let result = converter_solar_indices(SOLAR_INDICES_DATA.to_string(), &options).unwrap().payloads;
+        assert_eq!(result[0].1, "175.5");
+        let predicted_only = SOLAR_INDICES_DATA.replace(",OBS,", ",PRD,");
+        assert_eq!(converter_solar_indices(predicted_only, &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_records() {
+        // records keep values as received and UTC time of data whatever format of payloads
+        let options = ConvertOptions { payload_format: PayloadFormat::Scalar, precision: Some(0),
+                                       timezone: "Europe/Berlin".parse().unwrap(), ..Default::default() };
+        let result = converter_flux(FLUX_DATA_SHORT.to_string(), &options).unwrap();
+        assert_eq!(result.payloads, vec![("".to_string(), "0.0".to_string())]);
+        assert_eq!(to_line_protocol("sw", "noaa_flux", &result.records),
+                   ["sw,source=noaa_flux satellite=18i,flux_gt10mev=0.35,flux_gt50mev=0.14,flux_gt100mev=0.1,\
+                     flux_gt500mev=0.04,s_scale=0i 1714522200000000000"]);
+        let result = converter_kp_inst(KP_INST_DATA.to_string(), &options).unwrap();
+        assert_eq!(to_line_protocol("sw", "noaa_kp_inst", &result.records),
+                   ["sw,source=noaa_kp_inst kp=4.0 1714523340000000000"]);
+        // field types follow record types: satellite and scale are integers, flux is float
+        let result = converter_xray(XRAY_DATA.to_string(), &ConvertOptions::default()).unwrap();
+        assert_eq!(to_line_protocol("sw", "noaa_xray", &result.records),
+                   ["sw,source=noaa_xray satellite=16i,flux=0.0000231,flare_class=\"M2.3\",r_scale=1i \
+                     1714521660000000000"]);
+        let result = converter_solar_indices(SOLAR_INDICES_DATA.to_string(), &options).unwrap();
+        // daily values at midnight UTC
+        assert_eq!(to_line_protocol("sw", "solar_indices", &result.records),
+                   ["sw,source=solar_indices f107=175.5,sunspot_number=161.0,ap=7.0 1714521600000000000"]);
+        let result = converter_sw_forecast(SW_FORECAST_DATA.to_string(), &options).unwrap();
+        assert!(result.records.is_empty());
+    }
+
+    #[test]
+    fn test_converter_xray() {
+        let result = converter_xray(XRAY_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        // trailing record with null and 0.05-0.4 nm items are skipped
+        let expected = concat!(r#"{"time_tag":"00:01 01-05-2024","satellite":16,"flux":0.0000231,"#,
+                               r#""flare_class":"M2.3","r_scale":1}"#);
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+        let short_band = XRAY_DATA.replace("0.1-0.8nm", "0.05-0.4nm");
+        assert_eq!(converter_xray(short_band, &ConvertOptions::default()), Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_goes_mag() {
+        let result = converter_goes_mag(GOES_MAG_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        // trailing record with nulls is skipped
+        let expected = r#"{"time_tag":"00:01 01-05-2024","hp":98.66,"he":62.54,"hn":2.61}"#;
+        assert_eq!(result, vec![("".to_string(), expected.to_string())]);
+    }
+
+    #[test]
+    fn test_converter_goes_mag_no_data() {
+        let nulls = r#"[{"time_tag": "2024-05-01T00:02:00Z", "satellite": 16, "He": null, "Hp": null, "Hn": null}]"#;
+        assert_eq!(converter_goes_mag(nulls.to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+        assert_eq!(converter_goes_mag("[]".to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
+
+    #[test]
+    fn test_converter_sw_forecast() {
+        let result = converter_sw_forecast(SW_FORECAST_DATA.to_string(), &ConvertOptions::default()).unwrap().payloads;
+        assert_eq!(result.len(), 4);
+        assert_eq!(result[0].0, "");
+        let payload: serde_json::Value = serde_json::from_str(&result[0].1).unwrap();
+        assert_eq!(payload["kp"].as_array().unwrap().len(), 24);
+        assert_eq!(payload["kp"][0].to_string(), r#"{"date":"May 01 2024","hour":3,"value":4.67}"#);
+        assert_eq!(payload["srs"].as_array().unwrap().len(), 3);
+        assert_eq!(payload["srs"][0].to_string(), r#"{"date":"May 01 2024","s1":5,"s2":5,"s3":5,"s4":5,"s5":5}"#);
+        assert_eq!(payload["rb"].as_array().unwrap().len(), 3);
+        assert_eq!(payload["rb"][2].to_string(), r#"{"date":"May 03 2024","r1":35,"r2":35,"r3":5,"r4":5,"r5":5}"#);
+        assert_eq!(result[1], ("_kp_summary".to_string(),
+                               r#"{"observed":4.0,"expected":4.67,"expected_scale":"G1"}"#.to_string()));
+        assert_eq!(result[2], ("_kp_daily_max".to_string(),
+                               "[{\"date\":\"May 01 2024\",\"max_kp\":4.67,\"scale\":\"G1\"},\
+                                {\"date\":\"May 02 2024\",\"max_kp\":6.0,\"scale\":\"G2\"},\
+                                {\"date\":\"May 03 2024\",\"max_kp\":8.67,\"scale\":\"G4\"}]".to_string()));
+        assert_eq!(result[3], ("_window".to_string(), r#"{"start":"May 01 2024","end":"May 03 2024"}"#.to_string()));
+    }
+
+    #[test]
+    fn test_forecast_window_across_new_year() {
+        let record = |date: &str| SRSRBForecast { date: date.to_string(), s1: 5, s2: 5, s3: 5, s4: 5, s5: 5 };
+        let sw_data = SWForecast { srs: vec![record("Dec 31 2024"), record("Jan 02 2025")],
+                                   rb: vec![record("Jan 01 2025")], ..Default::default() };
+        assert_eq!(forecast_window(&sw_data), Some(ForecastWindow { start: "Dec 31 2024".to_string(),
+                                                                     end: "Jan 02 2025".to_string() }));
+        assert_eq!(forecast_window(&SWForecast::default()), None);
+    }
+
+    #[test]
+    fn test_kp_daily_max_below_scale() {
+        let kp = [KPForecast { date: "May 01 2024".to_string(), hour: 3, value: 2.33 },
+                  KPForecast { date: "May 01 2024".to_string(), hour: 6, value: 3.67 }];
+        assert_eq!(kp_daily_max(&kp), vec![KpDailyMax { date: "May 01 2024".to_string(), max_kp: 3.67, scale: None }]);
+    }
+
+    #[test]
+    fn test_primary_value_mut() {
+        let mut scalar: serde_json::Value = serde_json::from_str("4.0").unwrap();
+        assert_eq!(primary_value_mut(&mut scalar, "kp").unwrap().as_f64(), Some(4.0));
+        let mut object: serde_json::Value = serde_json::from_str(r#"{"time_tag":"00:29 01-05-2024","kp":3.67}"#).unwrap();
+        assert_eq!(primary_value_mut(&mut object, "kp").unwrap().as_f64(), Some(3.67));
+        assert!(primary_value_mut(&mut object, "time_tag").is_none());
+        let mut array: serde_json::Value = serde_json::from_str(r#"[{"kp":1.0},{"kp":2.33}]"#).unwrap();
+        assert_eq!(primary_value_mut(&mut array, "kp").unwrap().as_f64(), Some(2.33));
+    }
+
+    #[test]
+    fn test_converter_sw_forecast_point_topics() {
+        let options = ConvertOptions {
+            forecast_topics: ForecastTopics { kp: Some("kp_{date}_{hour}".to_string()), srs: None,
+                                              rb: Some("rb_{date}_{grade}".to_string()) },
+            ..Default::default()
+        };
+        let result = converter_sw_forecast(SW_FORECAST_DATA.to_string(), &options).unwrap().payloads;
+        // state, summary, daily max, window, 24 Kp values and 3 days of 5 RB grades
+        assert_eq!(result.len(), 4 + 24 + 15);
+        assert_eq!(result[4], ("_kp_2024-05-01_3".to_string(), "4.67".to_string()));
+        assert_eq!(result[27], ("_kp_2024-05-03_24".to_string(), "8.67".to_string()));
+        assert_eq!(result[28], ("_rb_2024-05-01_r1".to_string(), "55".to_string()));
+        assert_eq!(result[42], ("_rb_2024-05-03_r5".to_string(), "5".to_string()));
+    }
+
+    #[test]
+    fn test_html_page() {
+        let page = "\n<!DOCTYPE html>\n<html><body>Scheduled maintenance</body></html>";
+        let result = converter_kp_inst(page.to_string(), &ConvertOptions::default());
+        assert_eq!(result, Err(ConvertError::Html("JSON")));
+        assert_eq!(result.unwrap_err().to_string(), "expected JSON but got HTML page");
+        assert_eq!(converter_sw_forecast(page.to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Html("text forecast")));
+        assert_eq!(csv_to_json(page, &[]), Err(ConvertError::Html("CSV")));
+    }
+
+    #[test]
+    fn test_convert_error_kinds() {
+        let result = converter_kp("{".to_string(), &ConvertOptions::default());
+        assert!(matches!(&result, Err(ConvertError::Deserialize(_))));
+        assert!(result.unwrap_err().to_string().starts_with("deserilisation error: "));
+        let result = converter_sw_forecast("no tables here".to_string(), &ConvertOptions::default());
+        assert_eq!(result, Err(ConvertError::Parse("no forecast tables found".to_string())));
+        assert!(ConvertError::Empty("got no data").is_source_fault());
+        assert!(ConvertError::Html("JSON").is_source_fault());
+        assert!(!ConvertError::Serialize("key must be a string".to_string()).is_source_fault());
+    }
+
+    #[test]
+    fn test_topic_token() {
+        assert_eq!(forecast_date_token("May 01 2024"), "2024-05-01");
+        assert_eq!(forecast_date_token("Foo 1 #/+"), "Foo_1____");
+    }
+
+    #[test]
+    fn test_converter_sw_forecast_empty_data() {
+        assert_eq!(converter_sw_forecast("\n".to_string(), &ConvertOptions::default()),
+                   Err(ConvertError::Empty("got no data")));
+    }
 }
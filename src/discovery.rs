@@ -0,0 +1,170 @@
+use serde_json::json;
+
+use crate::converters::{DisplayTimezone, PayloadFormat};
+
+
+// Home Assistant MQTT sensor of source state, see https://www.home-assistant.io/integrations/sensor.mqtt/
+#[derive(Debug, Clone, Copy, Default)]
+pub struct THASensor {
+    pub name: &'static str,
+    // Material Design icon, e.g. "mdi:earth"
+    pub icon: Option<&'static str>,
+    // HA sensor device class, None - generic numeric sensor (e.g. Kp is dimensionless)
+    pub device_class: Option<&'static str>,
+    pub unit: Option<&'static str>,
+}
+
+// Where and how source state is published
+pub struct TSensorState<'a> {
+    pub topic: &'a str,
+    // companion attributes message, HA `json_attributes_topic`
+    pub attributes_topic: Option<&'a str>,
+    pub value_field: &'a str,
+    pub payload_format: PayloadFormat,
+    pub timezone: DisplayTimezone,
+}
+
+// Object id of sensor and name of its state topic level, e.g. "cubieboard_noaa_kp". Device name is trimmed and
+// MQTT level separator and wildcards in it are replaced, empty device name gives bare sensor name.
+pub fn object_id(device_name: &str, sensor_name: &str) -> String {
+    let device_name = device_name.trim().replace(['/', '+', '#'], "_");
+    if device_name.is_empty() {
+        sensor_name.to_string()
+    } else {
+        format!("{device_name}_{sensor_name}")
+    }
+}
+
+// Retained discovery configs as (object id, payload): numeric sensor of source state and, for JSON payloads
+// with known UTC offset, timestamp sensor of its data time
+pub fn sensor_configs(sensor: &THASensor, device_name: &str, sensor_name: &str, state: &TSensorState)
+                      -> Vec<(String, String)> {
+    let object_id = object_id(device_name, sensor_name);
+    let device = json!({ "identifiers": [device_name], "name": device_name });
+
+    let mut value = json!({
+        "name": sensor.name,
+        "unique_id": object_id,
+        "state_topic": state.topic,
+        "state_class": "measurement",
+        "device": device,
+    });
+    if let Some(template) = value_template(state.payload_format, &format!("v.{}", state.value_field)) {
+        value["value_template"] = template.into();
+    }
+    if let Some(icon) = sensor.icon {
+        value["icon"] = icon.into();
+    }
+    if let Some(device_class) = sensor.device_class {
+        value["device_class"] = device_class.into();
+    }
+    if let Some(unit) = sensor.unit {
+        value["unit_of_measurement"] = unit.into();
+    }
+    if let Some(attributes_topic) = state.attributes_topic {
+        value["json_attributes_topic"] = attributes_topic.into();
+    }
+    let mut configs = vec![(object_id.clone(), value.to_string())];
+
+    // published time tags are "%H:%M %d-%m-%Y" in display timezone
+    let offset = match state.timezone {
+        DisplayTimezone::Utc => Some("+0000".to_string()),
+        DisplayTimezone::Fixed(offset) => Some(offset.to_string().replace(':', "")),
+        DisplayTimezone::Local | DisplayTimezone::Named(_) => None,
+    };
+    let time_template = offset.and_then(|offset| {
+        value_template(state.payload_format, &format!("strptime(v.time_tag ~ ' {offset}', '%H:%M %d-%m-%Y %z')"))
+    });
+    if let Some(template) = time_template {
+        let time = json!({
+            "name": format!("{} time", sensor.name),
+            "unique_id": format!("{object_id}_time"),
+            "state_topic": state.topic,
+            "value_template": template,
+            "device_class": "timestamp",
+            "device": device,
+        });
+        configs.push((format!("{object_id}_time"), time.to_string()));
+    }
+    configs
+}
+
+// JSON state is either single record or list of records with the latest one last, scalar state is value itself
+fn value_template(payload_format: PayloadFormat, expression: &str) -> Option<String> {
+    match payload_format {
+        PayloadFormat::Json => {
+            let record = "{% set v = value_json[-1] if value_json[0] is defined else value_json %}";
+            Some(format!("{record}{{{{ {expression} }}}}"))
+        },
+        PayloadFormat::Scalar => None,
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const FLUX: THASensor = THASensor { name: "Proton flux", icon: Some("mdi:radioactive"), device_class: None,
+                                        unit: Some("pfu") };
+
+    fn flux_state(payload_format: PayloadFormat, timezone: DisplayTimezone) -> TSensorState<'static> {
+        TSensorState { topic: "space_weather/cubieboard_noaa_flux/state", attributes_topic: None,
+                       value_field: "flux_gt10mev", payload_format, timezone }
+    }
+
+    #[test]
+    fn test_sensor_configs_json() {
+        let configs = sensor_configs(&FLUX, "cubieboard", "noaa_flux", &flux_state(PayloadFormat::Json,
+                                                                                     DisplayTimezone::Utc));
+        assert_eq!(configs.len(), 2);
+        let (object_id, payload) = &configs[0];
+        assert_eq!(object_id, "cubieboard_noaa_flux");
+        let value: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(value["state_class"], "measurement");
+        assert_eq!(value["unit_of_measurement"], "pfu");
+        assert_eq!(value["icon"], "mdi:radioactive");
+        assert_eq!(value["state_topic"], "space_weather/cubieboard_noaa_flux/state");
+        assert_eq!(value["value_template"],
+                   "{% set v = value_json[-1] if value_json[0] is defined else value_json %}{{ v.flux_gt10mev }}");
+        assert!(value.get("device_class").is_none());
+
+        let (object_id, payload) = &configs[1];
+        assert_eq!(object_id, "cubieboard_noaa_flux_time");
+        let value: serde_json::Value = serde_json::from_str(payload).unwrap();
+        assert_eq!(value["device_class"], "timestamp");
+        assert!(value["value_template"].as_str().unwrap()
+                    .ends_with("{{ strptime(v.time_tag ~ ' +0000', '%H:%M %d-%m-%Y %z') }}"));
+    }
+
+    #[test]
+    fn test_object_id() {
+        assert_eq!(object_id("cubieboard", "noaa_kp"), "cubieboard_noaa_kp");
+        assert_eq!(object_id("", "noaa_kp"), "noaa_kp");
+        assert_eq!(object_id("  ", "noaa_kp"), "noaa_kp");
+        assert_eq!(object_id(" attic ", "noaa_kp"), "attic_noaa_kp");
+        assert_eq!(object_id("pi/attic#1+", "noaa_kp"), "pi_attic_1__noaa_kp");
+    }
+
+    #[test]
+    fn test_sensor_configs_scalar() {
+        let configs = sensor_configs(&FLUX, "cubieboard", "noaa_flux", &flux_state(PayloadFormat::Scalar,
+                                                                                     DisplayTimezone::Utc));
+        // scalar payload has no time tag
+        assert_eq!(configs.len(), 1);
+        let value: serde_json::Value = serde_json::from_str(&configs[0].1).unwrap();
+        assert!(value.get("value_template").is_none());
+    }
+
+    #[test]
+    fn test_sensor_configs_timezone() {
+        let timezone = "+03:00".parse().unwrap();
+        let configs = sensor_configs(&FLUX, "cubieboard", "noaa_flux", &flux_state(PayloadFormat::Json, timezone));
+        assert!(configs[1].1.contains("' +0300'"));
+        // offset of local timezone is unknown to HA
+        let configs = sensor_configs(&FLUX, "cubieboard", "noaa_flux", &flux_state(PayloadFormat::Json,
+                                                                                     DisplayTimezone::Local));
+        assert_eq!(configs.len(), 1);
+    }
+}
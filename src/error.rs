@@ -0,0 +1,67 @@
+use crate::converters::ConvertError;
+
+
+// Errors of the provider pipeline: fetching, converting and publishing of sources and configuration
+#[derive(Debug, Clone, PartialEq, thiserror::Error)]
+pub enum ProviderError {
+    // fetching source or posting to HTTP transmitter failed, message keeps status code or failure kind
+    #[error("{0}")]
+    Http(String),
+    // publish to broker failed or wasn't confirmed
+    #[error("{0}")]
+    Mqtt(String),
+    // converted payload can't be read back, e.g. for smoothing
+    #[error("deserilisation error: {0}")]
+    Parse(String),
+    #[error(transparent)]
+    Convert(#[from] ConvertError),
+    #[error("{0}")]
+    Config(String),
+    // e.g. several transmitters failed, all of them are reported
+    #[error("{}", .0.iter().map(ToString::to_string).collect::<Vec<_>>().join("; "))]
+    Multiple(Vec<ProviderError>),
+}
+
+impl ProviderError {
+    // Single error stays as is, so callers can match on its kind
+    pub fn join(mut errors: Vec<ProviderError>) -> Result<(), ProviderError> {
+        match errors.len() {
+            0 => Ok(()),
+            1 => Err(errors.remove(0)),
+            _ => Err(ProviderError::Multiple(errors)),
+        }
+    }
+
+    // Error of source data or its availability, next fetches may fail the same way
+    pub fn is_source_fault(&self) -> bool {
+        match self {
+            ProviderError::Http(_) => true,
+            ProviderError::Convert(e) => e.is_source_fault(),
+            _ => false,
+        }
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_join() {
+        assert_eq!(ProviderError::join(Vec::new()), Ok(()));
+        let error = ProviderError::Mqtt("MQTT publish error (localhost:1883)".to_string());
+        assert_eq!(ProviderError::join(vec![error.clone()]), Err(error.clone()));
+        let result = ProviderError::join(vec![error, ProviderError::Http("Webhook error".to_string())]);
+        assert_eq!(result.unwrap_err().to_string(), "MQTT publish error (localhost:1883); Webhook error");
+    }
+
+    #[test]
+    fn test_is_source_fault() {
+        assert!(ProviderError::Http("HTTP status 503 error".to_string()).is_source_fault());
+        assert!(ProviderError::from(ConvertError::Empty("got no data")).is_source_fault());
+        assert!(!ProviderError::from(ConvertError::Serialize("key must be a string".to_string())).is_source_fault());
+        assert!(!ProviderError::Mqtt("MQTT publish error".to_string()).is_source_fault());
+    }
+}
@@ -0,0 +1,128 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+
+
+// InfluxDB line protocol, see https://docs.influxdata.com/influxdb/v1/write_protocols/line_protocol_reference/
+
+// Field value, its type comes from type of record field, so it doesn't change with formatting of payload
+#[derive(Debug, Clone, PartialEq)]
+pub enum TFieldValue {
+    Float(f64),
+    Integer(i64),
+    Bool(bool),
+    Text(String),
+}
+
+impl From<f32> for TFieldValue {
+    // shortest decimal of f32, e.g. 0.33 and not 0.33000001311302185
+    fn from(value: f32) -> Self {
+        TFieldValue::Float(value.to_string().parse().unwrap_or(f64::from(value)))
+    }
+}
+
+impl From<f64> for TFieldValue {
+    fn from(value: f64) -> Self {
+        TFieldValue::Float(value)
+    }
+}
+
+impl From<u8> for TFieldValue {
+    fn from(value: u8) -> Self {
+        TFieldValue::Integer(value.into())
+    }
+}
+
+impl From<i64> for TFieldValue {
+    fn from(value: i64) -> Self {
+        TFieldValue::Integer(value)
+    }
+}
+
+impl From<bool> for TFieldValue {
+    fn from(value: bool) -> Self {
+        TFieldValue::Bool(value)
+    }
+}
+
+impl From<&str> for TFieldValue {
+    fn from(value: &str) -> Self {
+        TFieldValue::Text(value.to_string())
+    }
+}
+
+impl fmt::Display for TFieldValue {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            // float without fraction still has to differ from integer
+            TFieldValue::Float(value) if value.fract() == 0.0 && value.is_finite() => write!(f, "{value:.1}"),
+            TFieldValue::Float(value) => write!(f, "{value}"),
+            TFieldValue::Integer(value) => write!(f, "{value}i"),
+            TFieldValue::Bool(value) => write!(f, "{value}"),
+            TFieldValue::Text(text) => write!(f, "\"{}\"", text.replace('\\', "\\\\").replace('"', "\\\"")),
+        }
+    }
+}
+
+// Typed record of converter written as one line: unrounded values at UTC time of data
+#[derive(Debug, Clone, PartialEq)]
+pub struct TLineRecord {
+    // None - server time
+    pub time: Option<DateTime<Utc>>,
+    pub fields: Vec<(&'static str, TFieldValue)>,
+}
+
+// Makes lines `<measurement>,source=<source> <fields> [timestamp ns]`, one per record with fields
+pub fn to_line_protocol(measurement: &str, source: &str, records: &[TLineRecord]) -> Vec<String> {
+    let prefix = format!("{},source={}", escape(measurement, ", "), escape(source, ",= "));
+    records.iter().filter_map(|record| {
+        if record.fields.is_empty() {
+            return None;
+        }
+        let fields: Vec<String> = record.fields.iter()
+                                        .map(|(name, value)| format!("{}={value}", escape(name, ",= ")))
+                                        .collect();
+        let line = format!("{prefix} {}", fields.join(","));
+        match record.time.and_then(|time| time.timestamp_nanos_opt()) {
+            Some(timestamp) => Some(format!("{line} {timestamp}")),
+            None => Some(line),
+        }
+    }).collect()
+}
+
+fn escape(name: &str, special: &str) -> String {
+    name.chars().fold(String::new(), |mut out, c| {
+        if special.contains(c) {
+            out.push('\\');
+        }
+        out.push(c);
+        out
+    })
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    #[test]
+    fn test_line_protocol_records() {
+        let records = [
+            TLineRecord { time: Utc.with_ymd_and_hms(2024, 5, 1, 0, 5, 0).single(),
+                          fields: vec![("flux_gt10mev", 0.33f32.into()), ("satellite", 18u8.into()),
+                                       ("stale", false.into()), ("class", "C1.5 \"long\"".into())] },
+            TLineRecord { time: None, fields: vec![] },
+        ];
+        assert_eq!(to_line_protocol("space weather", "noaa_flux", &records),
+                   ["space\\ weather,source=noaa_flux flux_gt10mev=0.33,satellite=18i,stale=false,\
+                     class=\"C1.5 \\\"long\\\"\" 1714521900000000000"]);
+    }
+
+    #[test]
+    fn test_line_protocol_server_time() {
+        let records = [TLineRecord { time: None, fields: vec![("kp", 3.0f32.into())] }];
+        assert_eq!(to_line_protocol("space_weather", "noaa_kp_inst", &records),
+                   ["space_weather,source=noaa_kp_inst kp=3.0"]);
+    }
+}
@@ -0,0 +1,3389 @@
+// Fetching, converting and publishing of NOAA space weather data, `weather-provider` binary is a thin CLI over it.
+// Converters and parsers can be used alone, e.g. `converters::converter_kp` or `parse_sw_forecast`.
+extern crate chrono;
+extern crate rumqttc;
+extern crate envconfig;
+
+pub mod parsers;
+pub mod converters;
+pub mod metrics;
+pub mod circuit_breaker;
+pub mod scales;
+pub mod discovery;
+pub mod rate_limiter;
+pub mod influx;
+pub mod secret;
+pub mod error;
+pub mod schedule;
+
+use reqwest::Error;
+use std::future::Future;
+use std::pin::Pin;
+use tokio::task;
+use tokio::sync::{mpsc, oneshot, watch, Notify};
+use tokio::time::{Duration, interval_at, sleep};
+use std::time::Instant;
+use std::collections::{BTreeMap, HashMap, VecDeque};
+use std::sync::{Arc, Mutex};
+use envconfig::Envconfig;
+use rumqttc::{MqttOptions, AsyncClient, QoS};
+use rumqttc::v5::mqttbytes::v5::PublishProperties;
+use std::str::FromStr;
+use converters::*;
+use metrics::TMetrics;
+use circuit_breaker::TCircuitBreaker;
+use rate_limiter::TRateLimiter;
+use scales::Severity;
+use discovery::{THASensor, TSensorState};
+use secret::TSecret;
+use error::ProviderError;
+use schedule::TCronSchedule;
+use influx::TLineRecord;
+
+
+// Converter returns (topic suffix, payload) pairs, suffix is appended to source topic name, and typed records
+pub type TconvertFn = fn(String, &ConvertOptions) -> Result::<Converted, ConvertError>;
+pub type TconvertBytesFn = fn(Vec<u8>, &ConvertOptions) -> Result::<Converted, ConvertError>;
+
+// Converter declares body type of its source: UTF-8 text or raw bytes (e.g. images), first field is its name
+#[derive(Clone, Copy)]
+pub enum TConverter {
+    Text(&'static str, TconvertFn),
+    #[allow(dead_code)]     // no binary sources are configured yet
+    Bytes(&'static str, TconvertBytesFn),
+}
+
+// Text converter named after its function
+macro_rules! text_converter {
+    ($convert:ident) => {
+        TConverter::Text(stringify!($convert), $convert)
+    };
+}
+
+impl TConverter {
+    pub fn convert(&self, body: Vec<u8>, options: &ConvertOptions) -> Result::<Converted, ConvertError> {
+        match self {
+            Self::Text(_, convert) => {
+                let text = String::from_utf8(body)
+                    .map_err(|e| ConvertError::Deserialize(format!("body is not UTF-8 text: {e}")))?;
+                convert(text, options)
+            },
+            Self::Bytes(_, convert) => convert(body, options),
+        }
+    }
+    pub fn name(&self) -> &'static str {
+        match self {
+            Self::Text(name, _) | Self::Bytes(name, _) => name,
+        }
+    }
+}
+
+// Converters that source definitions reference by name
+pub type TConverterRegistry = HashMap<&'static str, TConverter>;
+
+pub fn converter_registry() -> TConverterRegistry {
+    [
+        text_converter!(converter_kp),
+        text_converter!(converter_kp_history),
+        text_converter!(converter_kp_inst),
+        text_converter!(converter_flux),
+        text_converter!(converter_electron_flux),
+        text_converter!(converter_xray),
+        text_converter!(converter_solar_indices),
+        text_converter!(converter_goes_mag),
+        text_converter!(converter_sw_forecast),
+    ].into_iter().map(|converter| (converter.name(), converter)).collect()
+}
+
+// Maps primary value to NOAA scale level, e.g. Kp to G-scale
+pub type TScaleFn = fn(f32) -> u8;
+
+// Rolling window keeps at most this many values per source, e.g. 1h of values fetched every second
+const ROLLING_MAX_SAMPLES: usize = 3600;
+
+// Topic of combined severity across all sources with scale
+const SUMMARY_TOPIC: &str = "space_weather_summary";
+
+// Leaf of topics unless overridden by SOURCE_<NAME>_TOPIC_SUFFIX, e.g. "<device>_noaa_kp/state"
+const DEFAULT_TOPIC_SUFFIX: &str = "state";
+
+// Topic of broker connection state published by connection handler
+const CONNECTION_TOPIC: &str = "connection";
+
+// Topic of heartbeat published by dedicated task, monitors detect dead provider by its absence
+const HEARTBEAT_TOPIC: &str = "heartbeat";
+
+// Prefix of command topics subscribed if MQTT_COMMANDS is set, command is leaf of topic, e.g. "command/refresh"
+const COMMAND_TOPIC_PREFIX: &str = "command/";
+
+// Publish errors (e.g. full queue during reconnect) are retried with growing delay
+const PUBLISH_ATTEMPTS: u32 = 3;
+const PUBLISH_RETRY_DELAY: Duration = Duration::from_millis(200);
+
+// Alert is raised when primary value rises above threshold and cleared when it drops below threshold - hysteresis
+#[derive(Clone)]
+pub struct TAlertOptions {
+    pub topic: &'static str,
+    pub threshold: f32,
+    pub hysteresis: f32,
+}
+
+// Per-source options applied by provider to converted payloads
+#[derive(Clone, Default)]
+pub struct TProvideOptions {
+    // field of payload holding primary value (e.g. "kp")
+    pub value_field: &'static str,
+    // smoothing factor of exponential moving average, None - publish raw value
+    pub ema_alpha: Option<f32>,
+    pub alert: Option<TAlertOptions>,
+    // scale letter and level of primary value, contributes to space weather summary
+    pub scale: Option<(char, TScaleFn)>,
+    // extra HTTP request headers, e.g. API key
+    pub headers: Vec<(String, String)>,
+    // payloads bigger than this are published with warning, brokers may drop them
+    pub max_payload_size: Option<usize>,
+    // publish without waiting on full outbound queue, newer payload replaces older unsent one
+    pub drop_oldest: bool,
+    // QoS 2 publishes wait until broker confirms delivery
+    pub publish: TPublishOptions,
+    // announced to Home Assistant with MQTT discovery if enabled
+    pub ha_sensor: Option<THASensor>,
+    // source publishes companion attributes message
+    pub has_attributes: bool,
+    // payloads are gzipped and base64 encoded, see compress_payload
+    pub compress: bool,
+    // payloads arriving sooner after last publish of their topic are skipped, None - every payload is published
+    pub min_publish_interval: Option<Duration>,
+    // min/max of primary value over this window are published to "_range" topic, None - not published
+    pub rolling_window: Option<Duration>,
+    // one-shot fetch, e.g. backfill: neither sends nor remembers ETag and Last-Modified
+    pub skip_cache_validators: bool,
+}
+
+// MQTT delivery of source payloads, transmitters without broker ignore it
+#[derive(Debug, Clone, PartialEq)]
+pub struct TPublishOptions {
+    pub qos: QoS,
+    pub retain: bool,
+    // leaf of topics without own one, e.g. "value", empty - bare device topic
+    pub topic_suffix: String,
+}
+
+impl Default for TPublishOptions {
+    fn default() -> Self {
+        Self { qos: QoS::AtLeastOnce, retain: false, topic_suffix: DEFAULT_TOPIC_SUFFIX.to_string() }
+    }
+}
+
+// Outcome of providing source without error
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub enum TProvideOutcome {
+    // fetched and published, or not modified since last fetch
+    Provided,
+    // fetch skipped as circuit of source is open
+    Skipped,
+}
+
+// Commands received on command topics
+#[derive(Debug, Clone, PartialEq)]
+pub enum TCommand {
+    // "refresh" with source name: provide source immediately
+    Refresh(String),
+    // "interval" with source name and interval, e.g. "noaa_kp_inst 1m": change request interval of source task
+    SetInterval(String, Duration),
+}
+
+impl TCommand {
+    pub fn parse(command: &str, payload: &str) -> Result<Self, String> {
+        let payload = payload.trim();
+        match command {
+            "refresh" => Ok(TCommand::Refresh(payload.to_string())),
+            "interval" => {
+                let (name, interval) = payload.split_once(char::is_whitespace)
+                    .ok_or_else(|| format!("interval command '{payload}' must be '<source> <interval>'"))?;
+                let interval = interval.parse::<TDuration>()?.0;
+                if interval < MIN_REQUEST_INTERVAL {
+                    return Err(format!("{name} is {interval:?}, minimal request interval is {MIN_REQUEST_INTERVAL:?}"));
+                }
+                Ok(TCommand::SetInterval(name.to_string(), interval))
+            },
+            _ => Err(format!("unknown command '{command}', expected refresh or interval")),
+        }
+    }
+}
+
+#[derive(Clone)]
+pub struct TWeatherSource {
+    pub source_url: String,
+    // tried when fetching from source URL fails, e.g. secondary GOES satellite
+    pub fallback_url: Option<String>,
+    pub mqtt_topic_name: &'static str,
+    pub request_interval: Duration,
+    // fetch times replacing request interval, e.g. shortly after NOAA updates
+    pub schedule: Option<TCronSchedule>,
+    pub convert: TConverter,
+    pub options: ConvertOptions,
+    pub provide_options: TProvideOptions,
+}
+
+pub type TBoxFuture<'a, T> = Pin<Box<dyn Future<Output = T> + Send + 'a>>;
+
+// Publishing side of the provider, implemented by MQTT transmitter and by fakes in tests
+pub trait Transmitter: Send + Sync {
+    fn send_to_broker<'a>(&'a self, topic: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>>;
+    // Doesn't wait for outbound queue, returns false if it's full
+    fn try_send_to_broker(&self, topic: &str, payload: &str, options: TPublishOptions) -> Result<bool, ProviderError>;
+    // QoS 2 publish completes when delivery is confirmed by broker with PUBCOMP
+    fn send_with_options<'a>(&'a self, topic: &'a str, payload: String, _options: TPublishOptions)
+                             -> TBoxFuture<'a, Result<(), ProviderError>> {
+        self.send_to_broker(topic, payload)
+    }
+    // Retained Home Assistant discovery config of sensor, transmitters without broker ignore it
+    fn send_discovery<'a>(&'a self, _object_id: &'a str, _payload: String)
+                          -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async { Ok(()) })
+    }
+    // Typed records of converted source data, transmitters of payloads ignore them
+    fn send_records<'a>(&'a self, _source: &'a str, _records: &'a [TLineRecord])
+                        -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async { Ok(()) })
+    }
+}
+
+// HTTP cache validators of the last response, sent back for conditional requests
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct TCacheValidators {
+    pub etag: Option<String>,
+    pub last_modified: Option<String>,
+}
+
+#[derive(Clone, Debug, PartialEq)]
+pub enum TFetchResult {
+    Modified { body: Vec<u8>, validators: TCacheValidators },
+    NotModified,
+}
+
+// Loading side of the provider, implemented by HTTP fetcher and by fakes in tests
+pub trait Fetcher: Send + Sync {
+    fn fetch<'a>(&'a self, url: &'a str, headers: &'a [(String, String)], validators: &'a TCacheValidators)
+                 -> TBoxFuture<'a, Result<TFetchResult, ProviderError>>;
+}
+
+pub struct TReqwestFetcher {
+    client: reqwest::Client,
+}
+
+impl TReqwestFetcher {
+    pub fn new(config: &Config) -> Result<Self, ProviderError> {
+        let mut builder = reqwest::Client::builder();
+        // without dedicated proxy reqwest still honors HTTP_PROXY/HTTPS_PROXY/NO_PROXY env vars
+        if let Some(proxy_url) = &config.http_proxy {
+            println!("Using HTTP proxy {proxy_url}");
+            let no_proxy = config.http_no_proxy.as_deref().and_then(reqwest::NoProxy::from_string);
+            let proxy = reqwest::Proxy::all(proxy_url)
+                .map_err(|e| ProviderError::Config(format!("HTTP proxy config error: {e}")))?
+                .no_proxy(no_proxy);
+            builder = builder.proxy(proxy);
+        }
+        // moved NOAA endpoint stays visible in log
+        let max_redirects = config.http_max_redirects;
+        builder = builder.redirect(reqwest::redirect::Policy::custom(move |attempt| {
+            if attempt.previous().len() > max_redirects {
+                return attempt.error(format!("more than {max_redirects} redirects"));
+            }
+            let from = attempt.previous().last().map(ToString::to_string).unwrap_or_default();
+            println!("\tHTTP redirect {} from {from} to {}", attempt.status(), attempt.url());
+            attempt.follow()
+        }));
+        // unreachable host fails after connect timeout, while slow download may take up to request timeout
+        if let Some(timeout) = config.http_connect_timeout.enabled() {
+            builder = builder.connect_timeout(timeout);
+        }
+        if let Some(timeout) = config.http_request_timeout.enabled() {
+            builder = builder.timeout(timeout);
+        }
+        // NOAA serves compressed JSON on request, decoded body is converted
+        builder = builder.gzip(true).deflate(true);
+        let client = builder.build().map_err(|e| ProviderError::Config(format!("HTTP client build error: {e}")))?;
+        Ok(Self { client })
+    }
+    async fn load_bytes(&self, url: &str, headers: &[(String, String)], validators: &TCacheValidators)
+                       -> Result::<TFetchResult, ProviderError> {
+        let http_error = |e: Error| ProviderError::Http(describe_http_error(&e));
+        let mut request = self.client.get(url);
+        for (name, value) in headers {
+            request = request.header(name, value);
+        }
+        if let Some(etag) = &validators.etag {
+            request = request.header(reqwest::header::IF_NONE_MATCH, etag);
+        }
+        if let Some(last_modified) = &validators.last_modified {
+            request = request.header(reqwest::header::IF_MODIFIED_SINCE, last_modified);
+        }
+        let response = request.send().await.map_err(http_error)?    // make GET request
+                .error_for_status().map_err(http_error)?;    // handling HTTP status
+        if response.status() == reqwest::StatusCode::NOT_MODIFIED {
+            return Ok(TFetchResult::NotModified);
+        }
+        // e.g. moved endpoint lands on error or login page, which isn't data of any source
+        let content_type = response.headers().get(reqwest::header::CONTENT_TYPE)
+                                   .and_then(|value| value.to_str().ok()).unwrap_or_default();
+        if content_type.starts_with("text/html") {
+            let redirected = if response.url().as_str() != url { " after redirect" } else { "" };
+            return Err(ProviderError::Http(format!("HTTP response of {}{redirected} is HTML page, not source data",
+                                                   response.url())));
+        }
+        let header_value = |name| response.headers().get(name)
+                                          .and_then(|value: &reqwest::header::HeaderValue| value.to_str().ok())
+                                          .map(|value| value.to_string());
+        let validators = TCacheValidators {
+            etag: header_value(reqwest::header::ETAG),
+            last_modified: header_value(reqwest::header::LAST_MODIFIED),
+        };
+        Ok(TFetchResult::Modified { body: response.bytes().await.map_err(http_error)?.to_vec(), validators })
+    }
+}
+
+impl Fetcher for TReqwestFetcher {
+    fn fetch<'a>(&'a self, url: &'a str, headers: &'a [(String, String)], validators: &'a TCacheValidators)
+                 -> TBoxFuture<'a, Result<TFetchResult, ProviderError>> {
+        Box::pin(async move {
+            self.load_bytes(url, headers, validators).await
+        })
+    }
+}
+
+// Keeps HTTP status code or failure kind in message, e.g. to tell 404 from 503 or timeout
+fn describe_http_error(e: &Error) -> String {
+    if let Some(status) = e.status() {
+        format!("HTTP status {} error: {e}", status.as_u16())
+    } else if e.is_timeout() && e.is_connect() {
+        format!("HTTP connect timeout error: {e}")
+    } else if e.is_timeout() {
+        format!("HTTP timeout error: {e}")
+    } else if e.is_connect() {
+        format!("HTTP connect error: {e}")
+    } else if e.is_redirect() {
+        format!("HTTP redirect error: {e}")
+    } else {
+        format!("HTTP reqwest error: {e}")
+    }
+}
+
+pub struct TWeatherProvider {
+    config: Arc<Config>,
+    fetcher: Box<dyn Fetcher>,
+    // every payload is published to all transmitters, e.g. local and cloud brokers
+    transmitters: Vec<Box<dyn Transmitter>>,
+    metrics: Arc<TMetrics>,
+    // last moving average value per source topic
+    ema_state: Mutex<HashMap<String, f64>>,
+    // raised alerts per alert topic
+    alert_state: Mutex<HashMap<String, bool>>,
+    // cache validators of last fetched data per source topic
+    cache_validators: Mutex<HashMap<String, TCacheValidators>>,
+    // circuit breakers per source topic
+    breakers: Mutex<HashMap<String, TCircuitBreaker>>,
+    // latest level per scale letter for space weather summary
+    scale_levels: Mutex<BTreeMap<char, u8>>,
+    // latest unsent payload per transmitter index and topic of `drop_oldest` sources
+    unsent: Mutex<HashMap<(usize, String), (String, TPublishOptions)>>,
+    // global limit of requests to data provider across all sources
+    rate_limiter: Mutex<TRateLimiter>,
+    // consecutive failures per tracked source topic for EXIT_AFTER_FAILURES
+    failure_streaks: Mutex<HashMap<String, u32>>,
+    // last publish time per topic of sources with minimal publish interval
+    last_publish: Mutex<HashMap<String, Instant>>,
+    // primary values with their receive time per source topic within rolling window
+    rolling: Mutex<HashMap<String, VecDeque<(Instant, f32)>>>,
+    // latest payloads per source topic in combined output mode and whether they changed since last publish
+    combined: Mutex<(BTreeMap<String, serde_json::Value>, bool)>,
+    // notified when all tracked sources fail
+    all_failing: Notify,
+}
+
+impl TWeatherProvider {
+    pub fn new(config: Arc<Config>, fetcher: Box<dyn Fetcher>, transmitters: Vec<Box<dyn Transmitter>>,
+           metrics: Arc<TMetrics>) -> Self {
+        Self {
+            fetcher,
+            transmitters,
+            metrics,
+            ema_state: Mutex::new(HashMap::new()),
+            alert_state: Mutex::new(HashMap::new()),
+            cache_validators: Mutex::new(HashMap::new()),
+            breakers: Mutex::new(HashMap::new()),
+            scale_levels: Mutex::new(BTreeMap::new()),
+            unsent: Mutex::new(HashMap::new()),
+            rate_limiter: Mutex::new(TRateLimiter::new(config.fetch_rate_limit, Instant::now())),
+            failure_streaks: Mutex::new(HashMap::new()),
+            last_publish: Mutex::new(HashMap::new()),
+            rolling: Mutex::new(HashMap::new()),
+            combined: Mutex::new((BTreeMap::new(), false)),
+            all_failing: Notify::new(),
+            config,
+        }
+    }
+    // Provides source out of schedule, data is fetched without cache validators, so it's published
+    // even if unchanged
+    pub async fn refresh(&self, source: &TWeatherSource) -> Result::<TProvideOutcome, ProviderError> {
+        self.cache_validators.lock().expect("Error when locking cache validators mutex")
+            .remove(source.mqtt_topic_name);
+        self.provide(source).await
+    }
+    pub async fn provide(&self, source: &TWeatherSource) -> Result::<TProvideOutcome, ProviderError> {
+        println!("\tProviding weather source {}", source.mqtt_topic_name);
+        if !self.with_breaker(source, |breaker| breaker.allow(Instant::now())) {
+            println!("\tCircuit of weather source {} is open, skip fetching", source.mqtt_topic_name);
+            return Ok(TProvideOutcome::Skipped);
+        }
+        let result = self.load_and_publish(source).await;
+        self.record_outcome(source, result.is_ok());
+        result.map(|_| TProvideOutcome::Provided)
+    }
+    async fn load_and_publish(&self, source: &TWeatherSource) -> Result::<(), ProviderError> {
+        let Converted { payloads, records } = match self.load_and_convert(source).await {
+            Ok(Some(converted)) => {
+                self.metrics.fetch_success(source.mqtt_topic_name);
+                self.with_breaker(source, |breaker| breaker.record_success());
+                converted
+            },
+            Ok(None) => {
+                self.with_breaker(source, |breaker| breaker.record_success());
+                println!("\tWeather source {} not modified, skip publishing", source.mqtt_topic_name);
+                return Ok(());
+            },
+            Err(e) => {
+                self.metrics.fetch_error(source.mqtt_topic_name);
+                // pausing fetches doesn't help with converter bugs, but failed probe of half-open circuit
+                // always reopens it, otherwise the circuit stays half-open and blocks source for good
+                self.with_breaker(source, |breaker| {
+                    if e.is_source_fault() || breaker.is_half_open() {
+                        breaker.record_failure(Instant::now());
+                    }
+                });
+                return Err(e);
+            },
+        };
+        let mut payloads = self.smooth(source, payloads)?;
+        if let Some((_, payload)) = payloads.iter().find(|(topic_suffix, _)| topic_suffix.is_empty()) {
+            self.metrics.latest(source.mqtt_topic_name, payload);
+        }
+        let value = Self::primary_value(source, &payloads);
+        if let Some(range) = value.and_then(|value| self.update_rolling(source, value, Instant::now())) {
+            payloads.push(("_range".to_string(), range));
+        }
+        let alert = value.and_then(|value| self.check_alert(source, value));
+        let summary = value.and_then(|value| self.update_summary(source, value));
+        self.publish(source, payloads, &records).await?;
+        if let Some((topic, payload)) = alert {
+            println!("\tAlert {topic} changed to {payload}");
+            self.send_to_topic(topic, payload, TPublishOptions::default()).await?;
+        }
+        if let Some(payload) = summary {
+            self.send_to_topic(SUMMARY_TOPIC, payload, TPublishOptions::default()).await?;
+        }
+        Ok(())
+    }
+    // Sources whose failures count towards EXIT_AFTER_FAILURES
+    pub fn track_failures<'a>(&self, topic_names: impl Iterator<Item = &'a str>) {
+        if self.config.exit_after_failures == 0 {
+            return;
+        }
+        let mut streaks = self.failure_streaks.lock().expect("Error when locking failure streaks mutex");
+        streaks.extend(topic_names.map(|name| (name.to_string(), 0)));
+    }
+    fn record_outcome(&self, source: &TWeatherSource, success: bool) {
+        let mut streaks = self.failure_streaks.lock().expect("Error when locking failure streaks mutex");
+        let Some(streak) = streaks.get_mut(source.mqtt_topic_name) else {
+            return;
+        };
+        *streak = if success { 0 } else { *streak + 1 };
+        if streaks.values().all(|streak| *streak >= self.config.exit_after_failures) {
+            self.all_failing.notify_one();
+        }
+    }
+    pub async fn wait_all_failing(&self) {
+        self.all_failing.notified().await
+    }
+    // Primary value of state payload
+    fn primary_value(source: &TWeatherSource, payloads: &[(String, String)]) -> Option<f32> {
+        let (_, payload) = payloads.iter().find(|(topic_suffix, _)| topic_suffix.is_empty())?;
+        let mut value: serde_json::Value = serde_json::from_str(payload).ok()?;
+        Some(primary_value_mut(&mut value, source.provide_options.value_field)?.as_f64()? as f32)
+    }
+    // Adds value to rolling window of source and returns payload with min/max of values within window
+    fn update_rolling(&self, source: &TWeatherSource, value: f32, now: Instant) -> Option<String> {
+        let window = source.provide_options.rolling_window?;
+        let mut rolling = self.rolling.lock().expect("Error when locking rolling window mutex");
+        let samples = rolling.entry(source.mqtt_topic_name.to_string()).or_default();
+        samples.push_back((now, value));
+        while samples.len() > ROLLING_MAX_SAMPLES
+              || samples.front().is_some_and(|(time, _)| now.duration_since(*time) > window) {
+            samples.pop_front();
+        }
+        let (min, max) = samples.iter().fold((f32::MAX, f32::MIN), |(min, max), (_, value)| {
+            (min.min(*value), max.max(*value))
+        });
+        // values are finite as they come from JSON, f32 is printed without f64 conversion noise
+        Some(format!(r#"{{"min":{min:?},"max":{max:?},"samples":{}}}"#, samples.len()))
+    }
+    // Remembers scale level of source and returns summary payload with the worst condition across sources
+    fn update_summary(&self, source: &TWeatherSource, value: f32) -> Option<String> {
+        let (scale, to_level) = source.provide_options.scale?;
+        let mut scale_levels = self.scale_levels.lock().expect("Error when locking scale levels mutex");
+        scale_levels.insert(scale, to_level(value));
+        let max_level = scale_levels.values().copied().max().unwrap_or_default();
+        let mut summary = serde_json::Map::new();
+        summary.insert("severity".to_string(), Severity::from_level(max_level).name().into());
+        for (scale, level) in scale_levels.iter() {
+            summary.insert(format!("{}_scale", scale.to_ascii_lowercase()), (*level).into());
+        }
+        Some(serde_json::Value::Object(summary).to_string())
+    }
+    fn with_breaker<T>(&self, source: &TWeatherSource, f: impl FnOnce(&mut TCircuitBreaker) -> T) -> T {
+        let mut breakers = self.breakers.lock().expect("Error when locking circuit breakers mutex");
+        let breaker = breakers.entry(source.mqtt_topic_name.to_string()).or_insert_with(|| {
+            TCircuitBreaker::new(self.config.breaker_failure_threshold,
+                                 Duration::from_secs(self.config.breaker_cooldown_s.into()))
+        });
+        f(breaker)
+    }
+    // Returns alert topic and payload when alert state of source changes
+    fn check_alert(&self, source: &TWeatherSource, value: f32) -> Option<(&'static str, String)> {
+        let alert = source.provide_options.alert.as_ref()?;
+
+        let mut alert_state = self.alert_state.lock().expect("Error when locking alert state mutex");
+        let raised = alert_state.get(alert.topic).copied().unwrap_or(false);
+        let new_raised = if raised { value >= alert.threshold - alert.hysteresis } else { value > alert.threshold };
+        if raised == new_raised {
+            return None;
+        }
+        alert_state.insert(alert.topic.to_string(), new_raised);
+        Some((alert.topic, if new_raised { "ON" } else { "OFF" }.to_string()))
+    }
+    // Replaces primary value of state payload with its EMA, raw payload is published to "_raw" topic
+    fn smooth(&self, source: &TWeatherSource, payloads: Vec<(String, String)>)
+              -> Result::<Vec<(String, String)>, ProviderError> {
+        let Some(alpha) = source.provide_options.ema_alpha else {
+            return Ok(payloads);
+        };
+        let mut result = Vec::with_capacity(payloads.len() + 1);
+        for (topic_suffix, payload) in payloads {
+            if !topic_suffix.is_empty() {
+                result.push((topic_suffix, payload));
+                continue;
+            }
+            let mut value: serde_json::Value = serde_json::from_str(&payload)
+                .map_err(|e| ProviderError::Parse(e.to_string()))?;
+            if let Some(primary) = primary_value_mut(&mut value, source.provide_options.value_field) {
+                let raw = primary.as_f64().unwrap_or_default();
+                let mut ema_state = self.ema_state.lock().expect("Error when locking EMA state mutex");
+                let ema = match ema_state.get(source.mqtt_topic_name) {
+                    Some(prev) => f64::from(alpha) * raw + (1.0 - f64::from(alpha)) * prev,
+                    None => raw,
+                };
+                ema_state.insert(source.mqtt_topic_name.to_string(), ema);
+                // Kp resolution is 1/3, so 2 decimals are enough
+                *primary = serde_json::json!((ema * 100.0).round() / 100.0);
+            }
+            result.push(("_raw".to_string(), payload));
+            result.push((topic_suffix, value.to_string()));
+        }
+        Ok(result)
+    }
+    // Publishes all payloads and records even if some of them fail, errors are joined
+    async fn publish(&self, source: &TWeatherSource, payloads: Vec<(String, String)>, records: &[TLineRecord])
+                     -> Result::<(), ProviderError> {
+        let mut errors = Vec::new();
+        for (topic_suffix, payload) in payloads {
+            if let Err(e) = self.send(source, &topic_suffix, payload).await {
+                errors.push(e);
+            }
+        }
+        if !records.is_empty() {
+            for transmitter in &self.transmitters {
+                if let Err(e) = transmitter.send_records(source.mqtt_topic_name, records).await {
+                    errors.push(e);
+                }
+            }
+        }
+        ProviderError::join(errors)
+    }
+    // Publishes Home Assistant discovery configs of sources, failure of one transmitter doesn't stop others
+    pub async fn announce(&self, sources: &[TWeatherSource]) -> Result::<(), ProviderError> {
+        let mut errors = Vec::new();
+        for source in sources {
+            let Some(sensor) = &source.provide_options.ha_sensor else {
+                continue;
+            };
+            // Home Assistant can't read compressed state
+            if source.provide_options.compress {
+                continue;
+            }
+            let topic_suffix = &source.provide_options.publish.topic_suffix;
+            let topic = TMQTTransmitter::make_full_topic(source.mqtt_topic_name, topic_suffix, &self.config);
+            let attributes_topic = TMQTTransmitter::make_full_topic(
+                &(source.mqtt_topic_name.to_string() + ATTRIBUTES_TOPIC_SUFFIX), topic_suffix, &self.config);
+            let state = TSensorState {
+                topic: &topic,
+                attributes_topic: source.provide_options.has_attributes.then_some(attributes_topic.as_str()),
+                value_field: source.provide_options.value_field,
+                payload_format: source.options.payload_format,
+                timezone: source.options.timezone,
+            };
+            let configs = discovery::sensor_configs(sensor, &self.config.mqtt_device_name, source.mqtt_topic_name,
+                                                    &state);
+            for (object_id, payload) in configs {
+                for transmitter in &self.transmitters {
+                    if let Err(e) = transmitter.send_discovery(&object_id, payload.clone()).await {
+                        errors.push(e);
+                    }
+                }
+            }
+        }
+        ProviderError::join(errors)
+    }
+    async fn wait_rate_limit(&self) {
+        let wait = self.rate_limiter.lock().expect("Error when locking rate limiter mutex").reserve(Instant::now());
+        if !wait.is_zero() {
+            println!("\tRate limit of requests reached, waiting {wait:?}");
+            sleep(wait).await;
+        }
+    }
+    // Returns None if data wasn't modified since last fetch
+    async fn load_and_convert(&self, source: &TWeatherSource) -> Result::<Option<Converted>, ProviderError> {
+        let skip_validators = source.provide_options.skip_cache_validators;
+        let validators = match skip_validators {
+            true => TCacheValidators::default(),
+            false => self.cache_validators.lock().expect("Error when locking cache validators mutex")
+                         .get(source.mqtt_topic_name).cloned().unwrap_or_default(),
+        };
+        let headers = &source.provide_options.headers;
+        self.wait_rate_limit().await;
+        let fetched = match (self.fetcher.fetch(&source.source_url, headers, &validators).await, &source.fallback_url) {
+            (Err(e), Some(fallback_url)) => {
+                println!("\tFetching {} failed: {e}, trying fallback {fallback_url}", source.source_url);
+                self.wait_rate_limit().await;
+                let fetched = self.fetcher.fetch(fallback_url, headers, &validators).await
+                                  .map_err(|fallback_e| ProviderError::Http(format!("{e}; fallback: {fallback_e}")))?;
+                println!("\tFetched weather source {} from fallback {fallback_url}", source.mqtt_topic_name);
+                fetched
+            },
+            (result, _) => result?,
+        };
+        let (raw_data, validators) = match fetched {
+            TFetchResult::Modified { body, validators } => (body, validators),
+            TFetchResult::NotModified => return Ok(None),
+        };
+        let converted = source.convert.convert(raw_data, &source.options)?;
+        // remember validators only for successfully converted data
+        if !skip_validators {
+            self.cache_validators.lock().expect("Error when locking cache validators mutex")
+                .insert(source.mqtt_topic_name.to_string(), validators);
+        }
+        Ok(Some(converted))
+    }
+    async fn send(&self, source: &TWeatherSource, topic_suffix: &str, payload: String) -> Result::<(), ProviderError> {
+        let mut topic = source.mqtt_topic_name.to_string() + topic_suffix;
+        if self.config.combined_topic.is_some() {
+            let value = serde_json::from_str(&payload).unwrap_or_else(|_| serde_json::Value::from(payload));
+            let mut combined = self.combined.lock().expect("Error when locking combined payloads mutex");
+            combined.0.insert(topic, value);
+            combined.1 = true;
+            return Ok(());
+        }
+        let mut payload = payload;
+        if source.provide_options.compress {
+            (topic, payload) = compress_payload(topic, &source.provide_options.publish.topic_suffix, &payload);
+        }
+        if let Some(warning) = check_payload_size(&topic, &payload, source.provide_options.max_payload_size) {
+            println!("\tWarning: {warning}");
+        }
+        let min_interval = source.provide_options.min_publish_interval;
+        let now = Instant::now();
+        if !self.publish_due(&topic, min_interval, now) {
+            println!("\tSkip publishing {topic}, it was published less than {min_interval:?} ago");
+            return Ok(());
+        }
+        let result = if source.provide_options.drop_oldest {
+            self.send_latest(&topic, payload, source.provide_options.publish.clone())
+        } else {
+            self.send_to_topic(&topic, payload, source.provide_options.publish.clone()).await
+        };
+        // failed publish doesn't delay next one
+        if result.is_ok() && min_interval.is_some() {
+            self.last_publish.lock().expect("Error when locking last publish mutex").insert(topic, now);
+        }
+        result
+    }
+    // Document of combined output mode, None if no source was updated since last call
+    fn take_combined(&self) -> Option<String> {
+        let mut combined = self.combined.lock().expect("Error when locking combined payloads mutex");
+        if !combined.1 {
+            return None;
+        }
+        combined.1 = false;
+        Some(serde_json::to_string(&combined.0).unwrap_or_default())
+    }
+    // Debounce of topic publishes, always due without minimal interval
+    fn publish_due(&self, topic: &str, min_interval: Option<Duration>, now: Instant) -> bool {
+        let Some(min_interval) = min_interval else {
+            return true;
+        };
+        let last_publish = self.last_publish.lock().expect("Error when locking last publish mutex");
+        match last_publish.get(topic) {
+            Some(last) => now.duration_since(*last) >= min_interval,
+            None => true,
+        }
+    }
+    // Payload waits in buffer while outbound queue of transmitter is full, so stalled broker doesn't block sources.
+    // Buffered payloads of all topics are flushed on every call.
+    fn send_latest(&self, topic: &str, payload: String, options: TPublishOptions) -> Result::<(), ProviderError> {
+        let mut unsent = self.unsent.lock().expect("Error when locking unsent payloads mutex");
+        let mut errors = Vec::new();
+        for (index, transmitter) in self.transmitters.iter().enumerate() {
+            if unsent.insert((index, topic.to_string()), (payload.clone(), options.clone())).is_some() {
+                println!("\tDropped older unsent payload of {topic}");
+            }
+            unsent.retain(|(unsent_index, unsent_topic), (unsent_payload, unsent_options)| {
+                if *unsent_index != index {
+                    return true;
+                }
+                match transmitter.try_send_to_broker(unsent_topic, unsent_payload, unsent_options.clone()) {
+                    Ok(sent) => !sent,
+                    Err(e) => {
+                        errors.push(e);
+                        false
+                    },
+                }
+            });
+        }
+        if !unsent.is_empty() {
+            println!("\tOutbound queue is full, {} payloads wait for next publish", unsent.len());
+        }
+        ProviderError::join(errors)
+    }
+    // Failure of one transmitter doesn't prevent publishing to others, errors are joined
+    async fn send_to_topic(&self, topic: &str, payload: String, options: TPublishOptions)
+                           -> Result::<(), ProviderError> {
+        let mut errors = Vec::new();
+        for transmitter in &self.transmitters {
+            if let Err(e) = Self::send_with_retry(transmitter.as_ref(), topic, payload.clone(), options.clone()).await {
+                errors.push(e);
+            }
+        }
+        ProviderError::join(errors)
+    }
+    async fn send_with_retry(transmitter: &dyn Transmitter, topic: &str, payload: String, options: TPublishOptions)
+                             -> Result::<(), ProviderError> {
+        let mut attempt = 1;
+        loop {
+            match transmitter.send_with_options(topic, payload.clone(), options.clone()).await {
+                Err(e) if attempt < PUBLISH_ATTEMPTS => {
+                    println!("\tPublish to {topic} failed (attempt {attempt}/{PUBLISH_ATTEMPTS}): {e}");
+                    sleep(PUBLISH_RETRY_DELAY * attempt).await;
+                    attempt += 1;
+                },
+                result => return result,
+            }
+        }
+    }
+}
+
+
+pub struct TMQTTSettings {
+    pub name: &'static str,
+    pub host: String,
+    pub port: u16,
+    pub config: Arc<Config>,
+    pub metrics: Arc<TMetrics>,
+    // receives commands, None - command topics aren't subscribed
+    pub commands: Option<mpsc::UnboundedSender<TCommand>>,
+}
+
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TMQTTProtocol {
+    #[default]
+    V311,
+    V5,
+}
+
+impl FromStr for TMQTTProtocol {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "3.1.1" | "3" => Ok(TMQTTProtocol::V311),
+            "5" | "5.0" => Ok(TMQTTProtocol::V5),
+            _ => Err(format!("unknown MQTT protocol version '{s}', expected 3.1.1 or 5")),
+        }
+    }
+}
+
+// Transport of MQTT connection, some managed brokers (e.g. AWS IoT Core) accept only MQTT over WebSockets
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum TMQTTTransport {
+    #[default]
+    Tcp,
+    Ws,
+    // WebSockets over TLS
+    Wss,
+}
+
+impl FromStr for TMQTTTransport {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        match s {
+            "tcp" => Ok(TMQTTTransport::Tcp),
+            "ws" => Ok(TMQTTTransport::Ws),
+            "wss" => Ok(TMQTTTransport::Wss),
+            _ => Err(format!("unknown MQTT transport '{s}', expected tcp, ws or wss")),
+        }
+    }
+}
+
+impl TMQTTTransport {
+    // WebSocket URL of broker, e.g. "wss://broker.example.com:443/mqtt", None - plain TCP
+    fn url(&self, host: &str, port: u16, path: &str) -> Option<String> {
+        let scheme = match self {
+            TMQTTTransport::Tcp => return None,
+            TMQTTTransport::Ws => "ws",
+            TMQTTTransport::Wss => "wss",
+        };
+        let separator = if path.starts_with('/') { "" } else { "/" };
+        Some(format!("{scheme}://{host}:{port}{separator}{path}"))
+    }
+
+    // Broker address and transport of MqttOptions, WebSocket URL is passed as broker address
+    fn broker(&self, host: &str, port: u16, path: &str) -> (String, rumqttc::Transport) {
+        match (self, self.url(host, port, path)) {
+            (TMQTTTransport::Ws, Some(url)) => (url, rumqttc::Transport::Ws),
+            (TMQTTTransport::Wss, Some(url)) => (url, rumqttc::Transport::wss_with_default_config()),
+            _ => (host.to_string(), rumqttc::Transport::Tcp),
+        }
+    }
+}
+
+#[derive(Clone)]
+enum TMQTTClient {
+    V311(AsyncClient),
+    // publishes carry user properties
+    V5(rumqttc::v5::AsyncClient),
+}
+
+impl TMQTTClient {
+    // Used from connection handler, which can't wait for outbound queue it drains itself
+    fn try_publish_retained(&self, topic: &str, payload: &str) -> Result<(), ProviderError> {
+        let result = match self {
+            TMQTTClient::V311(client) => {
+                client.try_publish(topic, QoS::AtLeastOnce, true, payload).map_err(|e| e.to_string())
+            },
+            TMQTTClient::V5(client) => {
+                client.try_publish(topic, rumqttc::v5::mqttbytes::QoS::AtLeastOnce, true, payload.to_string())
+                      .map_err(|e| e.to_string())
+            },
+        };
+        result.map_err(ProviderError::Mqtt)
+    }
+
+    // Used from connection handler on every CONNACK, session may be clean after reconnect
+    fn try_subscribe(&self, topic: &str) -> Result<(), ProviderError> {
+        let result = match self {
+            TMQTTClient::V311(client) => client.try_subscribe(topic, QoS::AtLeastOnce).map_err(|e| e.to_string()),
+            TMQTTClient::V5(client) => {
+                client.try_subscribe(topic, rumqttc::v5::mqttbytes::QoS::AtLeastOnce).map_err(|e| e.to_string())
+            },
+        };
+        result.map_err(ProviderError::Mqtt)
+    }
+}
+
+// Full prefix of command topics and sender of received commands
+type TCommandChannel = Option<(String, mpsc::UnboundedSender<TCommand>)>;
+
+// Broker connectivity as seen by connection handler, distinct from broker-side availability
+#[derive(Debug, Clone, Copy, PartialEq)]
+enum TConnectionState {
+    // not connected yet
+    Disconnected,
+    Connected,
+    // connection was lost, handler reconnects with backoff
+    Reconnecting,
+}
+
+impl TConnectionState {
+    // State after CONNACK (`connected`) or connection error
+    fn next(self, connected: bool) -> Self {
+        match (self, connected) {
+            (_, true) => TConnectionState::Connected,
+            (TConnectionState::Disconnected, false) => TConnectionState::Disconnected,
+            (_, false) => TConnectionState::Reconnecting,
+        }
+    }
+
+    fn name(self) -> &'static str {
+        match self {
+            TConnectionState::Disconnected => "disconnected",
+            TConnectionState::Connected => "connected",
+            TConnectionState::Reconnecting => "reconnecting",
+        }
+    }
+
+    // Publishes retained state on change, state published while reconnecting is delivered after reconnect,
+    // so flapping stays visible
+    fn update(&mut self, connected: bool, client: &TMQTTClient, topic: &str, broker: &str) {
+        let next = self.next(connected);
+        if next == *self {
+            return;
+        }
+        *self = next;
+        println!("MQTT connection state ({broker}): {}", next.name());
+        if let Err(e) = client.try_publish_retained(topic, next.name()) {
+            println!("Error during publishing MQTT connection state ({broker}): {e}");
+        }
+    }
+}
+
+// Senders waiting for PUBCOMP of QoS 2 publishes. Broker completes QoS 2 flows in publish order,
+// so every PUBCOMP is matched to the oldest waiter.
+type TPubCompWaiters = Arc<Mutex<VecDeque<oneshot::Sender<Result<(), String>>>>>;
+
+// Polling of event loop after error returns next error at once if broker stays unreachable,
+// so it never goes without delay even if reconnect delays are configured as 0
+const MIN_RECONNECT_DELAY: Duration = Duration::from_millis(100);
+
+// Delay before reconnect after `failures` failed attempts in a row, doubles from initial delay up to max one
+fn reconnect_delay(failures: u32, (initial, max): &(Duration, Duration)) -> Duration {
+    initial.saturating_mul(2u32.saturating_pow(failures)).min(*max).max(MIN_RECONNECT_DELAY)
+}
+
+// Missed PINGRESP means broker or network is gone while TCP connection still looks alive
+fn describe_connection_error(e: &rumqttc::ConnectionError) -> String {
+    match e {
+        rumqttc::ConnectionError::MqttState(rumqttc::StateError::AwaitPingResp) => {
+            "keep-alive timeout, no PINGRESP from broker".to_string()
+        },
+        e => e.to_string(),
+    }
+}
+
+fn describe_connection_error_v5(e: &rumqttc::v5::ConnectionError) -> String {
+    match e {
+        rumqttc::v5::ConnectionError::MqttState(rumqttc::v5::StateError::AwaitPingResp) => {
+            "keep-alive timeout, no PINGRESP from broker".to_string()
+        },
+        rumqttc::v5::ConnectionError::MqttState(rumqttc::v5::StateError::ServerDisconnect { reason_code,
+                                                                                            reason_string }) => {
+            format!("broker disconnected with {reason_code:?}{}",
+                    reason_string.as_ref().map(|reason| format!(" ({reason})")).unwrap_or_default())
+        },
+        e => e.to_string(),
+    }
+}
+
+pub struct TMQTTransmitter {
+    settings: TMQTTSettings,
+    client: TMQTTClient,
+    pubcomp_waiters: TPubCompWaiters,
+}
+
+impl TMQTTransmitter {
+    pub fn new(settings: TMQTTSettings) -> Result<(Self, task::JoinHandle<()>), ProviderError> {
+        let client_id = Self::make_client_id(settings.name, &settings.config);
+        println!("Using MQTT client id {client_id}");
+        let (broker_address, transport) = settings.config.mqtt_transport.broker(&settings.host, settings.port,
+                                                                                &settings.config.mqtt_ws_path);
+        let keep_alive = Duration::from_secs(settings.config.mqtt_keep_alive.into());
+        let capacity = settings.config.mqtt_queue_capacity;
+        let clean_session = settings.config.mqtt_clean_session;
+        println!("Connecting to MQTT broker {}:{} using protocol {:?}...", settings.host, settings.port,
+                 settings.config.mqtt_protocol);
+        let broker = format!("{}:{}", settings.host, settings.port);
+        let pubcomp_waiters = TPubCompWaiters::default();
+        let waiters = pubcomp_waiters.clone();
+        let reconnect = (Duration::from_secs(settings.config.mqtt_reconnect_delay_s.into()),
+                         Duration::from_secs(settings.config.mqtt_reconnect_max_delay_s.into()));
+        let state_topic = Self::make_full_topic(CONNECTION_TOPIC, DEFAULT_TOPIC_SUFFIX, &settings.config);
+        let commands: TCommandChannel = settings.commands.clone().map(|sender| {
+            (Self::make_full_topic(COMMAND_TOPIC_PREFIX, DEFAULT_TOPIC_SUFFIX, &settings.config), sender)
+        });
+
+        println!("Spawn Connection handler task");
+        // Connection handler task
+        // The `EventLoop` must be regularly polled in order to send, receive and process packets
+        // from the broker, i.e. move ahead. Polling after error reconnects.
+        let (client, handler) = match settings.config.mqtt_protocol {
+            TMQTTProtocol::V311 => {
+                let mut mqttoptions = MqttOptions::new(client_id, broker_address, settings.port);
+                mqttoptions.set_transport(transport);
+                mqttoptions.set_keep_alive(keep_alive);
+                mqttoptions.set_clean_session(clean_session);
+                if let Some((username, password)) = settings.config.mqtt_credentials() {
+                    mqttoptions.set_credentials(username, password);
+                }
+                let (client, mut eventloop) = AsyncClient::new(mqttoptions, capacity);
+                let state_client = TMQTTClient::V311(client.clone());
+                let handler = task::spawn(async move {
+                    println!("Connection handler task spawned");
+                    let mut failures = 0;
+                    let mut state = TConnectionState::Disconnected;
+                    loop {
+                        match eventloop.poll().await {
+                            Ok(rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) => {
+                                println!("Connected to MQTT broker {broker}");
+                                failures = 0;
+                                state.update(true, &state_client, &state_topic, &broker);
+                                Self::subscribe_commands(&commands, &state_client, &broker);
+                            },
+                            Ok(rumqttc::Event::Incoming(rumqttc::Packet::PubComp(_))) => {
+                                Self::complete_pubcomp(&waiters, Ok(()));
+                            },
+                            Ok(rumqttc::Event::Incoming(rumqttc::Packet::Publish(publish))) => {
+                                Self::forward_command(&commands, &publish.topic, &publish.payload);
+                            },
+                            Ok(_) => {},
+                            Err(e) => {
+                                let delay = reconnect_delay(failures, &reconnect);
+                                failures = failures.saturating_add(1);
+                                println!("MQTT connection error ({broker}): {}, reconnecting in {delay:?}",
+                                         describe_connection_error(&e));
+                                state.update(false, &state_client, &state_topic, &broker);
+                                // waiting publishes fail and may be retried
+                                waiters.lock().expect("Error when locking PUBCOMP waiters mutex").clear();
+                                sleep(delay).await;
+                            },
+                        }
+                    }
+                });
+                (TMQTTClient::V311(client), handler)
+            },
+            TMQTTProtocol::V5 => {
+                let mut mqttoptions = rumqttc::v5::MqttOptions::new(client_id, broker_address, settings.port);
+                mqttoptions.set_transport(transport);
+                mqttoptions.set_keep_alive(keep_alive);
+                mqttoptions.set_clean_start(clean_session);
+                if let Some((username, password)) = settings.config.mqtt_credentials() {
+                    mqttoptions.set_credentials(username, password);
+                }
+                let (client, mut eventloop) = rumqttc::v5::AsyncClient::new(mqttoptions, capacity);
+                let state_client = TMQTTClient::V5(client.clone());
+                let handler = task::spawn(async move {
+                    println!("Connection handler task spawned");
+                    let mut failures = 0;
+                    let mut state = TConnectionState::Disconnected;
+                    loop {
+                        match eventloop.poll().await {
+                            Ok(rumqttc::v5::Event::Incoming(rumqttc::v5::mqttbytes::v5::Packet::ConnAck(_))) => {
+                                println!("Connected to MQTT broker {broker}");
+                                failures = 0;
+                                state.update(true, &state_client, &state_topic, &broker);
+                                Self::subscribe_commands(&commands, &state_client, &broker);
+                            },
+                            Ok(rumqttc::v5::Event::Incoming(rumqttc::v5::mqttbytes::v5::Packet::Publish(publish))) => {
+                                Self::forward_command(&commands, &String::from_utf8_lossy(&publish.topic),
+                                                      &publish.payload);
+                            },
+                            Ok(rumqttc::v5::Event::Incoming(rumqttc::v5::mqttbytes::v5::Packet::PubComp(pubcomp))) => {
+                                let result = match pubcomp.reason {
+                                    rumqttc::v5::mqttbytes::v5::PubCompReason::Success => Ok(()),
+                                    reason => Err(format!("PUBCOMP reason {reason:?}")),
+                                };
+                                Self::complete_pubcomp(&waiters, result);
+                            },
+                            Ok(_) => {},
+                            Err(e) => {
+                                let delay = reconnect_delay(failures, &reconnect);
+                                failures = failures.saturating_add(1);
+                                println!("MQTT connection error ({broker}): {}, reconnecting in {delay:?}",
+                                         describe_connection_error_v5(&e));
+                                state.update(false, &state_client, &state_topic, &broker);
+                                // waiting publishes fail and may be retried
+                                waiters.lock().expect("Error when locking PUBCOMP waiters mutex").clear();
+                                sleep(delay).await;
+                            },
+                        }
+                    }
+                });
+                (TMQTTClient::V5(client), handler)
+            },
+        };
+
+        let transmitter = Self { settings, client, pubcomp_waiters };
+
+        Ok((transmitter, handler))
+    }
+
+    fn subscribe_commands(commands: &TCommandChannel, client: &TMQTTClient, broker: &str) {
+        if let Some((prefix, _)) = commands {
+            let topic = prefix.to_string() + "+";
+            if let Err(e) = client.try_subscribe(&topic) {
+                println!("Error during subscribing to command topic {topic} ({broker}): {e}");
+            }
+        }
+    }
+
+    // Sends command to command task, other publishes and wrong commands are ignored
+    fn forward_command(commands: &TCommandChannel, topic: &str, payload: &[u8]) {
+        let Some((prefix, sender)) = commands else {
+            return;
+        };
+        let Some(command) = topic.strip_prefix(prefix.as_str()) else {
+            return;
+        };
+        match TCommand::parse(command, &String::from_utf8_lossy(payload)) {
+            Ok(command) => {
+                println!("Received command {command:?}");
+                // command task is gone only on shutdown
+                let _ = sender.send(command);
+            },
+            Err(e) => println!("Wrong command on {topic}: {e}"),
+        }
+    }
+
+    fn complete_pubcomp(waiters: &TPubCompWaiters, result: Result<(), String>) {
+        let waiter = waiters.lock().expect("Error when locking PUBCOMP waiters mutex").pop_front();
+        // waiter may be gone after timeout
+        if let Some(waiter) = waiter {
+            let _ = waiter.send(result);
+        }
+    }
+
+    async fn publish(&self, topic: &str, full_topic: String, payload: String, qos: QoS, retain: bool)
+                     -> Result<(), ProviderError> {
+        let result = match &self.client {
+            TMQTTClient::V311(client) => {
+                client.publish(full_topic, qos, retain, payload).await.map_err(|e| e.to_string())
+            },
+            TMQTTClient::V5(client) => {
+                let properties = PublishProperties { user_properties: Self::make_user_properties(topic, &payload),
+                                                     ..Default::default() };
+                client.publish_with_properties(full_topic, Self::v5_qos(qos), retain, payload, properties).await
+                      .map_err(|e| e.to_string())
+            },
+        };
+        result.map_err(|e| ProviderError::Mqtt(format!("MQTT publish error ({}:{}): {e}", self.settings.host,
+                                                       self.settings.port)))
+    }
+
+    fn v5_qos(qos: QoS) -> rumqttc::v5::mqttbytes::QoS {
+        match qos {
+            QoS::AtMostOnce => rumqttc::v5::mqttbytes::QoS::AtMostOnce,
+            QoS::AtLeastOnce => rumqttc::v5::mqttbytes::QoS::AtLeastOnce,
+            QoS::ExactlyOnce => rumqttc::v5::mqttbytes::QoS::ExactlyOnce,
+        }
+    }
+
+    // client id must be unique per broker, so by default it's derived from the device name
+    fn make_client_id(name: &str, config: &Config) -> String {
+        match &config.mqtt_client_id {
+            Some(client_id) => client_id.clone(),
+            None => name.to_string() + "-" + &config.mqtt_device_name,
+        }
+    }
+
+    // MQTT v5 user properties: sensor name and timestamp of data (`time_tag` of payload or its last record)
+    fn make_user_properties(sensor_name: &str, payload: &str) -> Vec<(String, String)> {
+        let mut properties = vec![("source".to_string(), sensor_name.to_string())];
+        let value: Option<serde_json::Value> = serde_json::from_str(payload).ok();
+        let record = match &value {
+            Some(serde_json::Value::Array(records)) => records.last(),
+            value => value.as_ref(),
+        };
+        if let Some(time_tag) = record.and_then(|record| record.get("time_tag")).and_then(|tag| tag.as_str()) {
+            properties.push(("timestamp".to_string(), time_tag.to_string()));
+        }
+        properties
+    }
+
+    fn make_discovery_topic(object_id: &str, config: &Config) -> String {
+        format!("{}/{object_id}/config", config.mqtt_base_topic.trim_end_matches('/'))
+    }
+
+    // Sensor name may carry own leaf (e.g. "noaa_kp_inst/attributes"), otherwise `suffix` leaf is used
+    // (e.g. "state"), empty suffix - no leaf
+    pub fn make_full_topic(sensor_name: &str, suffix: &str, config: &Config) -> String {
+        let object_id = discovery::object_id(&config.mqtt_device_name, sensor_name);
+        // base topic may be configured with trailing slash or empty
+        let full_topic = match config.state_base_topic().trim_end_matches('/') {
+            "" => object_id,
+            base_topic => base_topic.to_string() + "/" + &object_id,
+        };
+        if sensor_name.contains('/') || suffix.is_empty() {
+            full_topic
+        } else {
+            full_topic + "/" + suffix
+        }
+    }
+}
+
+impl Transmitter for TMQTTransmitter {
+    fn send_to_broker<'a>(&'a self, topic: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            self.send_with_options(topic, payload, TPublishOptions::default()).await
+        })
+    }
+
+    fn send_with_options<'a>(&'a self, topic: &'a str, payload: String, options: TPublishOptions)
+                             -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            let full_topic = Self::make_full_topic(topic, &options.topic_suffix, &self.settings.config);
+            let TPublishOptions { qos, retain, .. } = options;
+            println!("\tMQTT publish topic {} with {qos:?}{} and payload: ", full_topic,
+                     if retain { ", retained" } else { "" });
+            println!("\t\t{:#}", payload);
+            if qos != QoS::ExactlyOnce {
+                self.publish(topic, full_topic, payload, qos, retain).await?;
+                self.settings.metrics.publish(topic);
+                return Ok(());
+            }
+            let (pubcomp_tx, pubcomp_rx) = oneshot::channel();
+            self.pubcomp_waiters.lock().expect("Error when locking PUBCOMP waiters mutex").push_back(pubcomp_tx);
+            let forget_waiter = || {
+                self.pubcomp_waiters.lock().expect("Error when locking PUBCOMP waiters mutex")
+                    .retain(|waiter| !waiter.is_closed());
+            };
+            if let Err(e) = self.publish(topic, full_topic.clone(), payload, qos, retain).await {
+                forget_waiter();
+                return Err(e);
+            }
+            let timeout = Duration::from_secs(self.settings.config.mqtt_ack_timeout_s.into());
+            let result = match tokio::time::timeout(timeout, pubcomp_rx).await {
+                Ok(Ok(result)) => result,
+                Ok(Err(_)) => Err("connection lost before PUBCOMP".to_string()),
+                Err(_) => {
+                    forget_waiter();
+                    Err(format!("no PUBCOMP within {timeout:?}"))
+                },
+            };
+            result.map_err(|e| ProviderError::Mqtt(format!("MQTT delivery of {full_topic} failed ({}:{}): {e}",
+                                                           self.settings.host, self.settings.port)))?;
+            println!("\tBroker confirmed delivery of {full_topic}");
+            self.settings.metrics.publish(topic);
+            Ok(())
+        })
+    }
+
+    fn send_discovery<'a>(&'a self, object_id: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            let full_topic = Self::make_discovery_topic(object_id, &self.settings.config);
+            println!("\tMQTT publish discovery config {full_topic}");
+            self.publish(object_id, full_topic, payload, QoS::AtLeastOnce, true).await
+        })
+    }
+
+    // PUBCOMPs are matched to waiters in publish order, so publish without waiter is downgraded from QoS 2 to 1
+    fn try_send_to_broker(&self, topic: &str, payload: &str, options: TPublishOptions) -> Result<bool, ProviderError> {
+        let full_topic = Self::make_full_topic(topic, &options.topic_suffix, &self.settings.config);
+        let TPublishOptions { qos, retain, .. } = options;
+        let qos = if qos == QoS::ExactlyOnce { QoS::AtLeastOnce } else { qos };
+        let result = match &self.client {
+            TMQTTClient::V311(client) => {
+                client.try_publish(&full_topic, qos, retain, payload).map_err(|e| {
+                    matches!(e, rumqttc::ClientError::TryRequest(_))
+                })
+            },
+            TMQTTClient::V5(client) => {
+                let properties = PublishProperties { user_properties: Self::make_user_properties(topic, payload),
+                                                     ..Default::default() };
+                client.try_publish_with_properties(&full_topic, Self::v5_qos(qos), retain, payload.to_string(),
+                                                   properties).map_err(|e| {
+                    matches!(e, rumqttc::v5::ClientError::TryRequest(_))
+                })
+            },
+        };
+        match result {
+            Ok(()) => {
+                println!("\tMQTT publish topic {full_topic} with payload: ");
+                println!("\t\t{:#}", payload);
+                self.settings.metrics.publish(topic);
+                Ok(true)
+            },
+            // request channel to event loop is full
+            Err(true) => Ok(false),
+            Err(false) => Err(ProviderError::Mqtt(format!("MQTT publish error ({}:{})", self.settings.host,
+                                                          self.settings.port))),
+        }
+    }
+}
+
+
+// Prints full topics and payloads instead of publishing, used to check single source with `--source`
+pub struct TStdoutTransmitter {
+    pub config: Arc<Config>,
+}
+
+impl Transmitter for TStdoutTransmitter {
+    fn send_to_broker<'a>(&'a self, topic: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+        self.send_with_options(topic, payload, TPublishOptions::default())
+    }
+
+    fn send_with_options<'a>(&'a self, topic: &'a str, payload: String, options: TPublishOptions)
+                             -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            self.try_send_to_broker(topic, &payload, options).map(|_| ())
+        })
+    }
+
+    fn try_send_to_broker(&self, topic: &str, payload: &str, options: TPublishOptions) -> Result<bool, ProviderError> {
+        println!("{} {payload}", TMQTTransmitter::make_full_topic(topic, &options.topic_suffix, &self.config));
+        Ok(true)
+    }
+}
+
+
+// POSTs payloads as JSON `{"topic": ..., "payload": ...}` to HTTP endpoint, e.g. InfluxDB or custom collector
+pub struct TWebhookTransmitter {
+    url: String,
+    client: reqwest::Client,
+}
+
+impl TWebhookTransmitter {
+    pub fn new(url: String) -> Result<Self, ProviderError> {
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()
+            .map_err(|e| ProviderError::Config(format!("HTTP client build error: {e}")))?;
+        println!("Posting payloads to webhook {url}");
+        Ok(Self { url, client })
+    }
+
+    // JSON payload is embedded as is, other payloads as string
+    fn make_body(topic: &str, payload: &str) -> serde_json::Value {
+        let payload: serde_json::Value = serde_json::from_str(payload).unwrap_or_else(|_| payload.into());
+        serde_json::json!({ "topic": topic, "payload": payload })
+    }
+
+    async fn post(client: reqwest::Client, url: String, body: serde_json::Value) -> Result<(), ProviderError> {
+        client.post(&url).json(&body).send().await
+              .and_then(|response| response.error_for_status())
+              .map(|_| ())
+              .map_err(|e| ProviderError::Http(format!("Webhook error ({url}): {e}")))
+    }
+}
+
+// Writes records of sources to InfluxDB in line protocol, e.g. to http://influxdb:8086/write?db=space_weather
+pub struct TInfluxTransmitter {
+    url: String,
+    // InfluxDB 2 API token
+    token: Option<String>,
+    measurement: String,
+    client: reqwest::Client,
+}
+
+impl TInfluxTransmitter {
+    pub fn new(config: &Config, url: String) -> Result<Self, ProviderError> {
+        let client = reqwest::Client::builder().timeout(WEBHOOK_TIMEOUT).build()
+            .map_err(|e| ProviderError::Config(format!("HTTP client build error: {e}")))?;
+        println!("Writing records to InfluxDB {url}");
+        Ok(Self { url, token: config.influxdb_token.clone().map(|token| token.0),
+                  measurement: config.influxdb_measurement.clone(), client })
+    }
+}
+
+impl Transmitter for TInfluxTransmitter {
+    // payloads aren't written, records of sources are
+    fn send_to_broker<'a>(&'a self, _topic: &'a str, _payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async { Ok(()) })
+    }
+
+    fn try_send_to_broker(&self, _topic: &str, _payload: &str, _options: TPublishOptions)
+                          -> Result<bool, ProviderError> {
+        Ok(true)
+    }
+
+    fn send_records<'a>(&'a self, source: &'a str, records: &'a [TLineRecord])
+                        -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            let lines = influx::to_line_protocol(&self.measurement, source, records);
+            if lines.is_empty() {
+                return Ok(());
+            }
+            let mut request = self.client.post(&self.url).body(lines.join("\n"));
+            if let Some(token) = &self.token {
+                request = request.header(reqwest::header::AUTHORIZATION, format!("Token {token}"));
+            }
+            request.send().await
+                   .and_then(|response| response.error_for_status())
+                   .map(|_| ())
+                   .map_err(|e| ProviderError::Http(format!("InfluxDB write error ({}): {e}", self.url)))
+        })
+    }
+}
+
+impl Transmitter for TWebhookTransmitter {
+    fn send_to_broker<'a>(&'a self, topic: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+        Box::pin(async move {
+            println!("\tWebhook post of {topic}");
+            Self::post(self.client.clone(), self.url.clone(), Self::make_body(topic, &payload)).await
+        })
+    }
+
+    // HTTP request is posted in background, its errors are only logged
+    fn try_send_to_broker(&self, topic: &str, payload: &str, _options: TPublishOptions) -> Result<bool, ProviderError> {
+        println!("\tWebhook post of {topic}");
+        let post = Self::post(self.client.clone(), self.url.clone(), Self::make_body(topic, payload));
+        task::spawn(async move {
+            if let Err(e) = post.await {
+                println!("\t{e}");
+            }
+        });
+        Ok(true)
+    }
+}
+
+
+#[derive(Envconfig, Debug)]
+pub struct Config {
+    #[envconfig(from = "MQTT_BROKER_HOST", default = "localhost")]
+    pub mqtt_host: String,
+
+    #[envconfig(from = "MQTT_BROKER_PORT", default = "1883")]
+    pub mqtt_port: u16,
+
+    #[envconfig(from = "MQTT_BROKER_KEEP_ALIVE", default = "5")]
+    pub mqtt_keep_alive: u16,
+
+    // secrets can be read from files, e.g. MQTT_BROKER_PASSWORD_FILE=/run/secrets/mqtt_password
+    #[envconfig(from = "MQTT_BROKER_USERNAME")]
+    pub mqtt_username: Option<String>,
+
+    #[envconfig(from = "MQTT_BROKER_PASSWORD")]
+    pub mqtt_password: Option<TSecret>,
+
+    // HA discovery base topic, state topics are published under it unless MQTT_STATE_BASE_TOPIC is set
+    #[envconfig(from = "MQTT_BROKER_BASE_TOPIC", default = "homeassistant/sensor")]
+    pub mqtt_base_topic: String,
+
+    // namespace of state topics, e.g. "space_weather"
+    #[envconfig(from = "MQTT_STATE_BASE_TOPIC")]
+    pub mqtt_state_base_topic: Option<String>,
+
+    #[envconfig(from = "MQTT_DEVICE_NAME", default = "cubieboard")]
+    pub mqtt_device_name: String,
+
+    // 3.1.1 or 5, v5 publishes carry source and timestamp user properties
+    #[envconfig(from = "MQTT_PROTOCOL_VERSION", default = "3.1.1")]
+    pub mqtt_protocol: TMQTTProtocol,
+
+    // tcp, ws or wss (MQTT over WebSockets), WebSocket URL is built from broker host, port and MQTT_WS_PATH
+    #[envconfig(from = "MQTT_TRANSPORT", default = "tcp")]
+    pub mqtt_transport: TMQTTTransport,
+
+    #[envconfig(from = "MQTT_WS_PATH", default = "/mqtt")]
+    pub mqtt_ws_path: String,
+
+    // additional brokers receiving the same data, comma separated "host:port" list
+    #[envconfig(from = "MQTT_EXTRA_BROKERS")]
+    pub mqtt_extra_brokers: Option<String>,
+
+    #[envconfig(from = "MQTT_CLIENT_ID")]     // default - "weather-provider-<device name>"
+    pub mqtt_client_id: Option<String>,
+
+    // false - broker keeps session of client id (e.g. QoS 1 messages) across reconnects, needs stable MQTT_CLIENT_ID
+    #[envconfig(from = "MQTT_CLEAN_SESSION", default = "true")]
+    pub mqtt_clean_session: bool,
+
+    // payload size in bytes above which warning is logged, can be overridden by SOURCE_<NAME>_MAX_PAYLOAD_SIZE
+    #[envconfig(from = "MQTT_MAX_PAYLOAD_SIZE")]
+    pub mqtt_max_payload_size: Option<usize>,
+
+    // Capacity of publish requests channel. Bigger queue buffers more messages (e.g. during reconnect)
+    // at the cost of memory, smaller one applies back-pressure earlier and blocks publishing sources.
+    #[envconfig(from = "MQTT_QUEUE_CAPACITY", default = "10")]
+    pub mqtt_queue_capacity: usize,
+
+    // forecast data points are also published to own topics named by templates, e.g. "kp_{date}_{hour}",
+    // "srs_{date}_{grade}", see ForecastTopics
+    #[envconfig(from = "FORECAST_KP_TOPIC")]
+    pub forecast_kp_topic: Option<String>,
+
+    #[envconfig(from = "FORECAST_SRS_TOPIC")]
+    pub forecast_srs_topic: Option<String>,
+
+    #[envconfig(from = "FORECAST_RB_TOPIC")]
+    pub forecast_rb_topic: Option<String>,
+
+    // publish forecast with QoS 2 and wait for PUBCOMP, unconfirmed publish is retried
+    #[envconfig(from = "MQTT_FORECAST_EXACTLY_ONCE", default = "false")]
+    pub mqtt_forecast_exactly_once: bool,
+
+    #[envconfig(from = "MQTT_ACK_TIMEOUT_S", default = "10")]
+    pub mqtt_ack_timeout_s: u16,
+
+    // reconnect delay after connection loss (e.g. keep-alive timeout) doubles with every failed attempt
+    #[envconfig(from = "MQTT_RECONNECT_DELAY_S", default = "1")]
+    pub mqtt_reconnect_delay_s: u16,
+
+    #[envconfig(from = "MQTT_RECONNECT_MAX_DELAY_S", default = "60")]
+    pub mqtt_reconnect_max_delay_s: u16,
+
+    // Request intervals: duration like "10m", "6h", "1h 30m", replaced per source by cron expression
+    // SOURCE_<NAME>_CRON, e.g. "2,32 * * * *"
+    #[envconfig(from = "KP_RELEASE_INTERVAL", default = "10m")]
+    pub kp_release_interval: TDuration,
+
+    #[envconfig(from = "KP_INST_INTERVAL", default = "5m")]
+    pub kp_inst_interval: TDuration,
+
+    // first fetch of interval sources waits for wall-clock boundary of interval, e.g. next :00/:10/:20 for 10m,
+    // so fetch times don't depend on start time of service
+    #[envconfig(from = "ALIGN_INTERVALS", default = "false")]
+    pub align_intervals: bool,
+
+    // json - full records, scalar - only the latest value as payload
+    #[envconfig(from = "KP_PAYLOAD_FORMAT", default = "json")]
+    pub kp_payload_format: PayloadFormat,
+
+    #[envconfig(from = "KP_INST_PAYLOAD_FORMAT", default = "json")]
+    pub kp_inst_payload_format: PayloadFormat,
+
+    #[envconfig(from = "FLUX_PAYLOAD_FORMAT", default = "json")]
+    pub flux_payload_format: PayloadFormat,
+
+    // unit of published proton flux: pfu, cm-2s-1sr-1 (same values) or m-2s-1sr-1, payloads get `unit` field,
+    // unset - pfu without label
+    #[envconfig(from = "FLUX_UNIT")]
+    pub flux_unit: Option<FluxUnit>,
+
+    // records of every GOES satellite are also published to own sub-topic, e.g. noaa_flux/goes18
+    #[envconfig(from = "FLUX_SPLIT_SATELLITES", default = "false")]
+    pub flux_split_satellites: bool,
+
+    // GOES >=2 MeV electron flux, scalar - flux value
+    #[envconfig(from = "ELECTRON_PAYLOAD_FORMAT", default = "json")]
+    pub electron_payload_format: PayloadFormat,
+
+    // GOES 0.1-0.8 nm X-ray flux with flare class and R-scale, scalar - flux value
+    #[envconfig(from = "XRAY_PAYLOAD_FORMAT", default = "json")]
+    pub xray_payload_format: PayloadFormat,
+
+    // daily F10.7, sunspot number and Ap from CelesTrak CSV, scalar - F10.7
+    #[envconfig(from = "SOLAR_INDICES_PAYLOAD_FORMAT", default = "json")]
+    pub solar_indices_payload_format: PayloadFormat,
+
+    // GOES magnetometer, scalar - Hp component
+    #[envconfig(from = "MAG_PAYLOAD_FORMAT", default = "json")]
+    pub mag_payload_format: PayloadFormat,
+
+    // timezone of published timestamps: UTC, local (system timezone, see TZ env var), fixed offset like +03:00
+    // or IANA name like Europe/Berlin
+    #[envconfig(from = "DISPLAY_TIMEZONE", default = "UTC")]
+    pub display_timezone: DisplayTimezone,
+
+    // original NOAA timestamp is added to payloads as `raw_time_tag`, e.g. to check timezone conversion
+    #[envconfig(from = "RAW_TIME_TAG", default = "false")]
+    pub raw_time_tag: bool,
+
+    // Data older than this by its own timestamp is published with `stale: true` and `age_seconds`, 0 - disabled.
+    // Kp is released every 3 hours with some delay.
+    #[envconfig(from = "KP_STALE_AFTER", default = "6h")]
+    pub kp_stale_after: TDuration,
+
+    #[envconfig(from = "KP_INST_STALE_AFTER", default = "15m")]
+    pub kp_inst_stale_after: TDuration,
+
+    #[envconfig(from = "FLUX_STALE_AFTER", default = "30m")]
+    pub flux_stale_after: TDuration,
+
+    // number of decimals of published Kp and flux values, unset - as received from NOAA
+    #[envconfig(from = "FLOAT_PRECISION")]
+    pub float_precision: Option<u8>,
+
+    // smoothing factor (0..1] of exponential moving average for instantaneous Kp, unset - no smoothing
+    #[envconfig(from = "KP_INST_EMA_ALPHA")]
+    pub kp_inst_ema_alpha: Option<f32>,
+
+    // min/max of instantaneous Kp and proton flux over this window are published to "_range" topics, 0 - disabled
+    #[envconfig(from = "ROLLING_WINDOW", default = "0")]
+    pub rolling_window: TDuration,
+
+    // instantaneous Kp value that raises alert, unset - no alerts
+    #[envconfig(from = "KP_ALERT_THRESHOLD")]
+    pub kp_alert_threshold: Option<f32>,
+
+    #[envconfig(from = "KP_ALERT_HYSTERESIS", default = "0.33")]
+    pub kp_alert_hysteresis: f32,
+
+    #[envconfig(from = "KP_BACKFILL", default = "false")]    // publish historical Kp records on startup
+    pub kp_backfill: bool,
+
+    #[envconfig(from = "KP_BACKFILL_RECORDS", default = "7")]    // number of last Kp records published by backfill
+    pub kp_backfill_records: usize,
+
+    #[envconfig(from = "WEATHER_HTTP_PROXY")]     // proxy URL for all fetches, e.g. http://proxy:3128
+    pub http_proxy: Option<String>,
+
+    #[envconfig(from = "WEATHER_NO_PROXY")]       // comma separated hosts fetched without proxy
+    pub http_no_proxy: Option<String>,
+
+    // redirects of fetches are logged and followed up to this depth, 0 - redirect is error
+    #[envconfig(from = "HTTP_MAX_REDIRECTS", default = "10")]
+    pub http_max_redirects: usize,
+
+    // DNS lookup and TCP/TLS connect of fetches, 0 - no limit
+    #[envconfig(from = "HTTP_CONNECT_TIMEOUT", default = "10s")]
+    pub http_connect_timeout: TDuration,
+
+    // whole fetch including download of body, 0 - no limit
+    #[envconfig(from = "HTTP_REQUEST_TIMEOUT", default = "60s")]
+    pub http_request_timeout: TDuration,
+
+    // max requests per minute across all sources, 0 - unlimited
+    #[envconfig(from = "FETCH_RATE_LIMIT_PER_MIN", default = "30")]
+    pub fetch_rate_limit: u32,
+
+    // consecutive failures that open source circuit, 0 - circuit breaker disabled
+    #[envconfig(from = "BREAKER_FAILURE_THRESHOLD", default = "5")]
+    pub breaker_failure_threshold: u32,
+
+    #[envconfig(from = "BREAKER_COOLDOWN_S", default = "1800")]     // 30 min
+    pub breaker_cooldown_s: u32,
+
+    // heartbeat with counter and timestamp is published to <device>_heartbeat topic, 0 - disabled
+    #[envconfig(from = "HEARTBEAT_INTERVAL", default = "0")]
+    pub heartbeat_interval: TDuration,
+
+    // wait of interval sources doubles with every failed provide up to this multiple of request interval and
+    // returns to request interval after success, 1 - no backoff
+    #[envconfig(from = "FAILURE_BACKOFF_MAX_FACTOR", default = "8")]
+    pub failure_backoff_max_factor: u32,
+
+    // process exits with nonzero code when every source failed this many times in a row, so orchestrator
+    // restarts it, 0 - never exit
+    #[envconfig(from = "EXIT_AFTER_FAILURES", default = "0")]
+    pub exit_after_failures: u32,
+
+    // combined output mode: payloads of all sources are published as one JSON document keyed by source topic
+    // to <device>_<COMBINED_TOPIC> every COMBINED_INTERVAL instead of own topics, alerts and summary stay
+    #[envconfig(from = "COMBINED_TOPIC")]
+    pub combined_topic: Option<String>,
+
+    // document is published only if some source was updated since previous one
+    #[envconfig(from = "COMBINED_INTERVAL", default = "5m")]
+    pub combined_interval: TDuration,
+
+    // publish retained Home Assistant discovery configs under MQTT_BROKER_BASE_TOPIC on startup
+    #[envconfig(from = "MQTT_DISCOVERY", default = "false")]
+    pub mqtt_discovery: bool,
+
+    // subscribe to "<device>_command/+" topics: source name published to "refresh" provides it immediately,
+    // "<source> <interval>" published to "interval" changes request interval of source until restart
+    #[envconfig(from = "MQTT_COMMANDS", default = "false")]
+    pub mqtt_commands: bool,
+
+    // payloads are also POSTed to this HTTP endpoint
+    #[envconfig(from = "WEBHOOK_URL")]
+    pub webhook_url: Option<String>,
+
+    // InfluxDB write endpoint, e.g. http://influxdb:8086/write?db=space_weather (v1)
+    // or http://influxdb:8086/api/v2/write?org=home&bucket=space_weather (v2, needs INFLUXDB_TOKEN)
+    #[envconfig(from = "INFLUXDB_WRITE_URL")]
+    pub influxdb_write_url: Option<String>,
+
+    #[envconfig(from = "INFLUXDB_TOKEN")]
+    pub influxdb_token: Option<TSecret>,
+
+    #[envconfig(from = "INFLUXDB_MEASUREMENT", default = "space_weather")]
+    pub influxdb_measurement: String,
+
+    #[envconfig(from = "METRICS_PORT", default = "0")]      // 0 - metrics server disabled
+    pub metrics_port: u16,
+}
+
+// Duration parsed by humantime: "90s", "10m", "6h", "1h 30m", "0" disables feature
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct TDuration(pub Duration);
+
+impl FromStr for TDuration {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        humantime::parse_duration(s.trim()).map(TDuration)
+            .map_err(|e| format!("wrong duration '{s}', expected e.g. 10m or 6h: {e}"))
+    }
+}
+
+impl TDuration {
+    // zero duration disables feature
+    pub fn enabled(&self) -> Option<Duration> {
+        (!self.0.is_zero()).then_some(self.0)
+    }
+}
+
+// Former names of duration env vars, their values are plain seconds
+const SECONDS_ALIASES: [(&str, &str); 7] = [("KP_RELEASE_INTERVAL", "KP_RELEASE_INTERVAL_S"),
+                                            ("KP_INST_INTERVAL", "KP_INST_INTERVAL_S"),
+                                            ("ROLLING_WINDOW", "ROLLING_WINDOW_S"),
+                                            ("HTTP_CONNECT_TIMEOUT", "HTTP_CONNECT_TIMEOUT_S"),
+                                            ("HTTP_REQUEST_TIMEOUT", "HTTP_REQUEST_TIMEOUT_S"),
+                                            ("HEARTBEAT_INTERVAL", "HEARTBEAT_INTERVAL_S"),
+                                            ("COMBINED_INTERVAL", "COMBINED_INTERVAL_S")];
+
+// Sets duration env vars from their `_S` aliases, so old configs keep working; new name wins if both are set
+pub fn resolve_seconds_aliases(env: &mut HashMap<String, String>) -> Result<(), String> {
+    for (name, alias) in SECONDS_ALIASES {
+        let Some(seconds) = env.get(alias) else { continue };
+        let seconds = seconds.trim().parse::<u64>()
+            .map_err(|_| format!("{alias} must be number of seconds, got '{seconds}', use {name} for durations"))?;
+        if !env.contains_key(name) {
+            println!("{alias} is deprecated, use {name}={seconds}s");
+            env.insert(name.to_string(), format!("{seconds}s"));
+        }
+    }
+    Ok(())
+}
+
+// can be overridden per source by SOURCE_<NAME>_URL
+pub const NOAA_BASE_URL: &str = "https://services.swpc.noaa.gov";
+// daily indices, observed ones are followed by NOAA predictions
+pub const CELESTRAK_SW_URL: &str = "https://celestrak.org/SpaceData/SW-Last5Years.csv";
+// whole POST of payload to WEBHOOK_URL
+const WEBHOOK_TIMEOUT: Duration = Duration::from_secs(10);
+// Shorter intervals would hammer NOAA, zero one makes busy loop
+const MIN_REQUEST_INTERVAL: Duration = Duration::from_secs(10);
+
+impl Config {
+    // username with password, password alone isn't sent
+    pub fn mqtt_credentials(&self) -> Option<(String, String)> {
+        let password = self.mqtt_password.as_ref().map(|password| password.0.clone()).unwrap_or_default();
+        self.mqtt_username.clone().map(|username| (username, password))
+    }
+
+    fn state_base_topic(&self) -> &str {
+        self.mqtt_state_base_topic.as_deref().unwrap_or(&self.mqtt_base_topic)
+    }
+
+    // main broker followed by MQTT_EXTRA_BROKERS
+    pub fn brokers(&self) -> Result<Vec<(String, u16)>, ProviderError> {
+        let mut brokers = vec![(self.mqtt_host.clone(), self.mqtt_port)];
+        if let Some(spec) = &self.mqtt_extra_brokers {
+            let extra = parse_brokers(spec)
+                .map_err(|e| ProviderError::Config(format!("wrong MQTT_EXTRA_BROKERS: {e}")))?;
+            brokers.extend(extra);
+        }
+        Ok(brokers)
+    }
+
+    pub fn validate(&self) -> Result<(), ProviderError> {
+        let intervals = [("KP_RELEASE_INTERVAL", self.kp_release_interval),
+                         ("KP_INST_INTERVAL", self.kp_inst_interval)];
+        for (name, TDuration(interval)) in intervals {
+            if interval < MIN_REQUEST_INTERVAL {
+                return Err(ProviderError::Config(format!("{name} is {interval:?}, minimal request interval is \
+                                                          {MIN_REQUEST_INTERVAL:?}")));
+            }
+        }
+        if let (Some(connect), Some(request)) = (self.http_connect_timeout.enabled(),
+                                                 self.http_request_timeout.enabled()) {
+            if connect > request {
+                return Err(ProviderError::Config(format!("HTTP_CONNECT_TIMEOUT is {connect:?}, it can't exceed \
+                                                          HTTP_REQUEST_TIMEOUT {request:?}")));
+            }
+        }
+        if self.combined_topic.is_some() && self.combined_interval.enabled().is_none() {
+            return Err(ProviderError::Config("COMBINED_INTERVAL must be positive with COMBINED_TOPIC".to_string()));
+        }
+        Ok(())
+    }
+}
+
+
+// Parses headers spec "Name: value; Other-Name: value", values may reference env vars as ${VAR}
+fn parse_headers(spec: &str, env: impl Fn(&str) -> Option<String>) -> Result<Vec<(String, String)>, String> {
+    let mut headers = Vec::new();
+    for header in spec.split(';').map(str::trim).filter(|header| !header.is_empty()) {
+        let (name, value) = header.split_once(':').ok_or_else(|| format!("header '{header}' has no ':'"))?;
+        let mut value = value.trim().to_string();
+        while let Some(start) = value.find("${") {
+            let end = value[start..].find('}').ok_or_else(|| format!("unclosed env var reference in '{header}'"))?;
+            let var_name = &value[start + 2..start + end];
+            let var_value = env(var_name).ok_or_else(|| format!("env var {var_name} is not set"))?;
+            value.replace_range(start..start + end + 1, &var_value);
+        }
+        headers.push((name.trim().to_string(), value));
+    }
+    Ok(headers)
+}
+
+// Parses comma separated "host:port" list of MQTT brokers
+fn parse_brokers(spec: &str) -> Result<Vec<(String, u16)>, String> {
+    spec.split(',').map(str::trim).filter(|broker| !broker.is_empty()).map(|broker| {
+        let (host, port) = broker.rsplit_once(':').ok_or_else(|| format!("broker '{broker}' has no port"))?;
+        let port = port.parse().map_err(|e| format!("wrong port of broker '{broker}': {e}"))?;
+        Ok((host.to_string(), port))
+    }).collect()
+}
+
+// Compressed payload is published to topic with ".gz.b64" leaf, e.g. "<device>_noaa_sw_forecast/state.gz.b64",
+// consumers decode it with `base64 -d | gunzip`
+fn compress_payload(topic: String, topic_suffix: &str, payload: &str) -> (String, String) {
+    use base64::Engine;
+    let topic = if topic.contains('/') || topic_suffix.is_empty() {
+        topic + ".gz.b64"
+    } else {
+        format!("{topic}/{topic_suffix}.gz.b64")
+    };
+    (topic, base64::engine::general_purpose::STANDARD.encode(gzip(payload.as_bytes())))
+}
+
+fn gzip(data: &[u8]) -> Vec<u8> {
+    use std::io::Write;
+    let mut encoder = flate2::write::GzEncoder::new(Vec::new(), flate2::Compression::default());
+    // writes to Vec don't fail
+    encoder.write_all(data).and_then(|_| encoder.finish()).unwrap_or_default()
+}
+
+fn check_payload_size(topic: &str, payload: &str, max_size: Option<usize>) -> Option<String> {
+    let max_size = max_size?;
+    (payload.len() > max_size).then(|| format!("payload of {topic} is {} bytes, limit is {max_size} bytes",
+                                               payload.len()))
+}
+
+// Sample payloads bundled into binary for `--self-test`, by source topic
+pub const SELF_TEST_FIXTURES: [(&str, &str); 8] = [
+    ("noaa_kp", include_str!("../tests/fixtures/noaa-planetary-k-index.json")),
+    ("noaa_kp_inst", include_str!("../tests/fixtures/planetary_k_index_1m.json")),
+    ("noaa_flux", include_str!("../tests/fixtures/integral-protons-plot-6-hour.json")),
+    ("noaa_electron_flux", include_str!("../tests/fixtures/integral-electrons-plot-3-day-short.json")),
+    ("noaa_xray", include_str!("../tests/fixtures/xrays-6-hour-short.json")),
+    ("solar_indices", include_str!("../tests/fixtures/SW-Last5Years-short.csv")),
+    ("noaa_goes_mag", include_str!("../tests/fixtures/magnetometers-1-day-short.json")),
+    ("noaa_sw_forecast", include_str!("../tests/fixtures/3-day-forecast.txt")),
+];
+
+// Converts bundled sample of source without network, returns number of payloads
+pub fn self_test_source(source: &TWeatherSource) -> Result<usize, String> {
+    let (_, sample) = SELF_TEST_FIXTURES.iter().find(|(topic, _)| *topic == source.mqtt_topic_name)
+                                        .ok_or("no bundled sample payload")?;
+    let payloads = source.convert.convert(sample.as_bytes().to_vec(), &source.options)
+                         .map_err(|e| e.to_string())?.payloads;
+    if payloads.is_empty() {
+        return Err("no payloads converted".to_string());
+    }
+    Ok(payloads.len())
+}
+
+// Per-source env var name, e.g. SOURCE_NOAA_KP_HEADERS
+fn source_env_name(topic_name: &str, setting: &str) -> String {
+    format!("SOURCE_{}_{setting}", topic_name.to_uppercase())
+}
+
+// Everything about source in one place: where data is fetched, how it's converted, published and announced
+// to Home Assistant. Weather source is built from it with SOURCE_<NAME>_* env overrides applied.
+pub struct TSourceConfig {
+    pub name: &'static str,
+    pub url: String,
+    pub fallback_url: Option<String>,
+    pub request_interval: Duration,
+    // name in converter registry, can be overridden by SOURCE_<NAME>_CONVERTER
+    pub converter: String,
+    pub options: ConvertOptions,
+    pub provide_options: TProvideOptions,
+    // friendly name, icon, device class and unit of HA sensor
+    pub ha_sensor: Option<THASensor>,
+    pub qos: QoS,
+    pub retain: bool,
+}
+
+impl TSourceConfig {
+    pub fn new(name: &'static str, url: String, request_interval: Duration, converter: &str) -> Self {
+        Self { name, url, fallback_url: None, request_interval, converter: converter.to_string(),
+               options: ConvertOptions::default(),
+               provide_options: TProvideOptions::default(), ha_sensor: None, qos: QoS::AtLeastOnce, retain: false }
+    }
+
+    // `max_payload_size` applies unless overridden per source
+    pub fn build(self, env: impl Fn(&str) -> Option<String>, max_payload_size: Option<usize>,
+             registry: &TConverterRegistry) -> Result<TWeatherSource, ProviderError> {
+        let setting = |name: &str| env(&source_env_name(self.name, name));
+        let wrong = |name: &str, e: String| ProviderError::Config(format!("wrong {name}: {e}"));
+        let max_payload_size = match setting("MAX_PAYLOAD_SIZE") {
+            Some(size) => Some(size.parse().map_err(|e| wrong("MAX_PAYLOAD_SIZE", format!("{e}")))?),
+            None => max_payload_size,
+        };
+        let headers = match setting("HEADERS") {
+            Some(spec) => parse_headers(&spec, &env).map_err(|e| wrong("HEADERS", e))?,
+            None => Vec::new(),
+        };
+        let qos = match setting("QOS") {
+            Some(qos) => parse_qos(&qos).map_err(ProviderError::Config)?,
+            None => self.qos,
+        };
+        let retain = match setting("RETAIN") {
+            Some(retain) => retain.parse().map_err(|e| wrong("RETAIN", format!("{e}")))?,
+            None => self.retain,
+        };
+        let converter = setting("CONVERTER").unwrap_or(self.converter);
+        let convert = *registry.get(converter.as_str())
+                               .ok_or_else(|| ProviderError::Config(format!("unknown converter '{converter}'")))?;
+        let compress = match setting("COMPRESS").as_deref() {
+            Some("gzip") => true,
+            Some("none") => false,
+            Some(compress) => return Err(wrong("COMPRESS", format!("'{compress}', expected gzip or none"))),
+            None => self.provide_options.compress,
+        };
+        // e.g. "value", empty - bare device topic
+        let topic_suffix = match setting("TOPIC_SUFFIX") {
+            Some(suffix) if suffix.contains(['+', '#']) || suffix.starts_with('/') => {
+                return Err(wrong("TOPIC_SUFFIX", format!("'{suffix}' isn't topic leaf")));
+            },
+            Some(suffix) => suffix,
+            None => DEFAULT_TOPIC_SUFFIX.to_string(),
+        };
+        let schedule = match setting("CRON") {
+            Some(expression) => Some(expression.parse().map_err(|e| wrong("CRON", e))?),
+            None => None,
+        };
+        // e.g. "30s" or "5m", zero disables
+        let min_publish_interval = match setting("MIN_PUBLISH_INTERVAL") {
+            Some(interval) => interval.parse::<TDuration>().map_err(|e| wrong("MIN_PUBLISH_INTERVAL", e))?.enabled(),
+            None => self.provide_options.min_publish_interval,
+        };
+        Ok(TWeatherSource {
+            source_url: setting("URL").unwrap_or(self.url),
+            fallback_url: setting("FALLBACK_URL").or(self.fallback_url),
+            mqtt_topic_name: self.name,
+            request_interval: self.request_interval,
+            schedule,
+            convert,
+            options: self.options,
+            provide_options: TProvideOptions {
+                headers,
+                max_payload_size,
+                ha_sensor: self.ha_sensor,
+                publish: TPublishOptions { qos, retain, topic_suffix },
+                compress,
+                min_publish_interval,
+                ..self.provide_options
+            },
+        })
+    }
+}
+
+fn parse_qos(qos: &str) -> Result<QoS, String> {
+    match qos {
+        "0" => Ok(QoS::AtMostOnce),
+        "1" => Ok(QoS::AtLeastOnce),
+        "2" => Ok(QoS::ExactlyOnce),
+        _ => Err(format!("wrong QoS '{qos}', expected 0, 1 or 2")),
+    }
+}
+
+const VALIDATE_TIMEOUT: Duration = Duration::from_secs(10);
+
+// Connects to broker and disconnects for `--validate-config`, client id is distinct from the service one,
+// so running service isn't kicked off the broker
+pub async fn check_mqtt_connection(config: &Config, host: &str, port: u16) -> Result<(), ProviderError> {
+    let (broker_address, transport) = config.mqtt_transport.broker(host, port, &config.mqtt_ws_path);
+    let client_id = TMQTTransmitter::make_client_id("weather-provider", config) + "-validate";
+    let keep_alive = Duration::from_secs(config.mqtt_keep_alive.into());
+    let connect = async {
+        match config.mqtt_protocol {
+            TMQTTProtocol::V311 => {
+                let mut mqttoptions = MqttOptions::new(client_id, broker_address, port);
+                mqttoptions.set_transport(transport);
+                mqttoptions.set_keep_alive(keep_alive);
+                if let Some((username, password)) = config.mqtt_credentials() {
+                    mqttoptions.set_credentials(username, password);
+                }
+                let (client, mut eventloop) = AsyncClient::new(mqttoptions, 1);
+                while !matches!(eventloop.poll().await.map_err(|e| describe_connection_error(&e))?,
+                                rumqttc::Event::Incoming(rumqttc::Packet::ConnAck(_))) {}
+                client.disconnect().await.map_err(|e| e.to_string())?;
+                // sends DISCONNECT
+                let _ = eventloop.poll().await;
+            },
+            TMQTTProtocol::V5 => {
+                let mut mqttoptions = rumqttc::v5::MqttOptions::new(client_id, broker_address, port);
+                mqttoptions.set_transport(transport);
+                mqttoptions.set_keep_alive(keep_alive);
+                if let Some((username, password)) = config.mqtt_credentials() {
+                    mqttoptions.set_credentials(username, password);
+                }
+                let (client, mut eventloop) = rumqttc::v5::AsyncClient::new(mqttoptions, 1);
+                while !matches!(eventloop.poll().await.map_err(|e| describe_connection_error_v5(&e))?,
+                                rumqttc::v5::Event::Incoming(rumqttc::v5::mqttbytes::v5::Packet::ConnAck(_))) {}
+                client.disconnect().await.map_err(|e| e.to_string())?;
+                let _ = eventloop.poll().await;
+            },
+        }
+        Ok::<(), String>(())
+    };
+    tokio::time::timeout(VALIDATE_TIMEOUT, connect).await
+        .unwrap_or_else(|_| Err(format!("no CONNACK within {VALIDATE_TIMEOUT:?}")))
+        .map_err(ProviderError::Mqtt)
+}
+
+// Built-in NOAA sources configured by env, SOURCE_<NAME>_* overrides are applied when they are built
+pub fn builtin_source_configs(config: &Config) -> [TSourceConfig; 8] {
+    [
+        TSourceConfig {
+            options: ConvertOptions { payload_format: config.kp_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      precision: config.float_precision,
+                                      stale_after: config.kp_stale_after.enabled(),
+                                      ..Default::default() },
+            provide_options: TProvideOptions { value_field: "kp", ..Default::default() },
+            ha_sensor: Some(THASensor { name: "Planetary Kp index", icon: Some("mdi:earth"), ..Default::default() }),
+            ..TSourceConfig::new("noaa_kp", format!("{NOAA_BASE_URL}/products/noaa-planetary-k-index.json"),
+                                 config.kp_release_interval.0, "converter_kp")
+        },
+        TSourceConfig {
+            options: ConvertOptions { payload_format: config.kp_inst_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      precision: config.float_precision,
+                                      stale_after: config.kp_inst_stale_after.enabled(),
+                                      ..Default::default() },
+            provide_options: TProvideOptions {
+                value_field: "kp",
+                ema_alpha: config.kp_inst_ema_alpha,
+                alert: config.kp_alert_threshold.map(|threshold| TAlertOptions {
+                    topic: "kp_alert",
+                    threshold,
+                    hysteresis: config.kp_alert_hysteresis,
+                }),
+                scale: Some(('G', scales::kp_to_g_scale)),
+                rolling_window: config.rolling_window.enabled(),
+                drop_oldest: true,
+                has_attributes: true,
+                ..Default::default()
+            },
+            ha_sensor: Some(THASensor { name: "Estimated Kp index", icon: Some("mdi:earth"), ..Default::default() }),
+            ..TSourceConfig::new("noaa_kp_inst", format!("{NOAA_BASE_URL}/json/planetary_k_index_1m.json"),
+                                 config.kp_inst_interval.0, "converter_kp_inst")
+        },
+        TSourceConfig {
+            fallback_url: Some(format!("{NOAA_BASE_URL}/json/goes/secondary/integral-protons-plot-6-hour.json")),
+            options: ConvertOptions { payload_format: config.flux_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      precision: config.float_precision,
+                                      stale_after: config.flux_stale_after.enabled(),
+                                      split_by_satellite: config.flux_split_satellites,
+                                      flux_unit: config.flux_unit,
+                                      ..Default::default() },
+            provide_options: TProvideOptions {
+                value_field: "flux_gt10mev",
+                // scale thresholds are in pfu
+                scale: Some(('S', match config.flux_unit {
+                    Some(FluxUnit::PerM2) => |flux| scales::proton_flux_to_s_scale(flux / 1e4),
+                    _ => scales::proton_flux_to_s_scale as TScaleFn,
+                })),
+                rolling_window: config.rolling_window.enabled(),
+                ..Default::default()
+            },
+            ha_sensor: Some(THASensor { name: "Proton flux >=10 MeV", icon: Some("mdi:radioactive"),
+                                        unit: Some(config.flux_unit.unwrap_or_default().label()),
+                                        ..Default::default() }),
+            ..TSourceConfig::new("noaa_flux",
+                                 format!("{NOAA_BASE_URL}/json/goes/primary/integral-protons-plot-6-hour.json"),
+                                 config.kp_inst_interval.0, "converter_flux")
+        },
+        TSourceConfig {
+            fallback_url: Some(format!("{NOAA_BASE_URL}/json/goes/secondary/integral-electrons-plot-3-day.json")),
+            options: ConvertOptions { payload_format: config.electron_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      precision: config.float_precision,
+                                      stale_after: config.flux_stale_after.enabled(),
+                                      ..Default::default() },
+            provide_options: TProvideOptions { value_field: "flux_gt2mev", ..Default::default() },
+            ha_sensor: Some(THASensor { name: "Electron flux >=2 MeV", icon: Some("mdi:radioactive"),
+                                        unit: Some("pfu"), ..Default::default() }),
+            ..TSourceConfig::new("noaa_electron_flux",
+                                 format!("{NOAA_BASE_URL}/json/goes/primary/integral-electrons-plot-3-day.json"),
+                                 config.kp_inst_interval.0, "converter_electron_flux")
+        },
+        TSourceConfig {
+            fallback_url: Some(format!("{NOAA_BASE_URL}/json/goes/secondary/xrays-6-hour.json")),
+            options: ConvertOptions { payload_format: config.xray_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      stale_after: config.flux_stale_after.enabled(),
+                                      ..Default::default() },
+            provide_options: TProvideOptions {
+                value_field: "flux",
+                scale: Some(('R', |flux| scales::xray_flux_to_r_scale(f64::from(flux)))),
+                ..Default::default()
+            },
+            ha_sensor: Some(THASensor { name: "X-ray flux 0.1-0.8 nm", icon: Some("mdi:white-balance-sunny"),
+                                        unit: Some("W/m²"), ..Default::default() }),
+            ..TSourceConfig::new("noaa_xray", format!("{NOAA_BASE_URL}/json/goes/primary/xrays-6-hour.json"),
+                                 config.kp_inst_interval.0, "converter_xray")
+        },
+        TSourceConfig {
+            options: ConvertOptions { payload_format: config.solar_indices_payload_format,
+                                      precision: config.float_precision,
+                                      ..Default::default() },
+            provide_options: TProvideOptions { value_field: "f107", ..Default::default() },
+            ha_sensor: Some(THASensor { name: "Solar radio flux F10.7", icon: Some("mdi:white-balance-sunny"),
+                                        unit: Some("sfu"), ..Default::default() }),
+            ..TSourceConfig::new("solar_indices", CELESTRAK_SW_URL.to_string(), config.kp_release_interval.0,
+                                 "converter_solar_indices")
+        },
+        TSourceConfig {
+            fallback_url: Some(format!("{NOAA_BASE_URL}/json/goes/secondary/magnetometers-1-day.json")),
+            options: ConvertOptions { payload_format: config.mag_payload_format,
+                                      timezone: config.display_timezone,
+                                      raw_time_tag: config.raw_time_tag,
+                                      precision: config.float_precision,
+                                      ..Default::default() },
+            provide_options: TProvideOptions { value_field: "hp", ..Default::default() },
+            ha_sensor: Some(THASensor { name: "GOES magnetometer Hp", icon: Some("mdi:magnet"), unit: Some("nT"),
+                                        ..Default::default() }),
+            ..TSourceConfig::new("noaa_goes_mag", format!("{NOAA_BASE_URL}/json/goes/primary/magnetometers-1-day.json"),
+                                 config.kp_inst_interval.0, "converter_goes_mag")
+        },
+        TSourceConfig {
+            options: ConvertOptions {
+                forecast_topics: ForecastTopics { kp: config.forecast_kp_topic.clone(),
+                                                  srs: config.forecast_srs_topic.clone(),
+                                                  rb: config.forecast_rb_topic.clone() },
+                ..Default::default()
+            },
+            qos: if config.mqtt_forecast_exactly_once { QoS::ExactlyOnce } else { QoS::AtLeastOnce },
+            ..TSourceConfig::new("noaa_sw_forecast", format!("{NOAA_BASE_URL}/text/3-day-forecast.txt"),
+                                 config.kp_release_interval.0, "converter_sw_forecast")
+        },
+    ]
+}
+
+// Kp history provided once at startup if KP_BACKFILL is set, overridden by SOURCE_NOAA_KP_HISTORY_*
+pub fn backfill_source_config(config: &Config) -> TSourceConfig {
+    TSourceConfig {
+        options: ConvertOptions { timezone: config.display_timezone,
+                                  precision: config.float_precision,
+                                  raw_time_tag: config.raw_time_tag,
+                                  history_records: Some(config.kp_backfill_records),
+                                  ..Default::default() },
+        provide_options: TProvideOptions { skip_cache_validators: true, ..Default::default() },
+        ..TSourceConfig::new("noaa_kp_history",
+                             format!("{NOAA_BASE_URL}/products/noaa-planetary-k-index.json"),
+                             config.kp_release_interval.0, "converter_kp_history")
+    }
+}
+
+pub fn describe_source(source: &TWeatherSource) -> String {
+    let mut description = format!("{}\n\turl: {}", source.mqtt_topic_name, source.source_url);
+    if let Some(fallback_url) = &source.fallback_url {
+        description += &format!("\n\tfallback url: {fallback_url}");
+    }
+    match &source.schedule {
+        Some(schedule) => description += &format!("\n\tschedule: {schedule} (UTC)"),
+        None => description += &format!("\n\tinterval: {:?}", source.request_interval),
+    }
+    description + &format!("\n\tconverter: {}", source.convert.name())
+}
+
+// Provides weather source only once
+pub fn start_backfill_task(wprovider_ref: Arc<TWeatherProvider>, ws: TWeatherSource) {
+    println!("Starting backfill for weather source {} ...", ws.mqtt_topic_name);
+
+    task::spawn(async move {
+        match wprovider_ref.provide(&ws).await {
+            Ok(TProvideOutcome::Provided) => println!("\tBackfill done for ws {}", ws.mqtt_topic_name),
+            Ok(TProvideOutcome::Skipped) => println!("\tBackfill of ws {} skipped", ws.mqtt_topic_name),
+            Err(e) => println!("\tError during backfill of weather source {}: {e}", ws.mqtt_topic_name),
+        }
+    });
+}
+
+fn heartbeat_payload(counter: u64, now: chrono::DateTime<chrono::Utc>) -> String {
+    serde_json::json!({
+        "counter": counter,
+        "timestamp": now.to_rfc3339_opts(chrono::SecondsFormat::Secs, true),
+    }).to_string()
+}
+
+// Publishes heartbeat independently of weather sources, counter restarts with provider
+pub fn start_heartbeat_task(wprovider_ref: Arc<TWeatherProvider>, period: Duration) {
+    println!("Starting heartbeat task every {period:?} ...");
+
+    task::spawn(async move {
+        let mut interval = interval_at(tokio::time::Instant::now(), period);
+        for counter in 1.. {
+            interval.tick().await;
+            let payload = heartbeat_payload(counter, chrono::Utc::now());
+            if let Err(e) = wprovider_ref.send_to_topic(HEARTBEAT_TOPIC, payload, TPublishOptions::default()).await {
+                println!("\tError during publishing heartbeat: {e}");
+            }
+        }
+    });
+}
+
+// Publishes latest payloads of all sources as one document in combined output mode
+pub fn start_combined_task(wprovider_ref: Arc<TWeatherProvider>, topic: String, period: Duration) {
+    println!("Starting combined publish task every {period:?} to {topic} ...");
+
+    task::spawn(async move {
+        let mut interval = interval_at(tokio::time::Instant::now() + period, period);
+        loop {
+            interval.tick().await;
+            let Some(payload) = wprovider_ref.take_combined() else {
+                println!("\tNo weather source updated, skip combined publish");
+                continue;
+            };
+            if let Err(e) = wprovider_ref.send_to_topic(&topic, payload, TPublishOptions::default()).await {
+                println!("\tError during publishing combined payload: {e}");
+            }
+        }
+    });
+}
+
+// Executes commands one by one, commands for unknown sources are ignored
+pub fn start_command_task(wprovider_ref: Arc<TWeatherProvider>, sources: Vec<TWeatherSource>,
+                          intervals: HashMap<&'static str, watch::Sender<Duration>>,
+                          mut commands: mpsc::UnboundedReceiver<TCommand>) -> task::JoinHandle<()> {
+    println!("Starting command task ...");
+
+    task::spawn(async move {
+        while let Some(command) = commands.recv().await {
+            match command {
+                TCommand::Refresh(name) => {
+                    let Some(source) = sources.iter().find(|source| source.mqtt_topic_name == name) else {
+                        println!("\tRefresh command for unknown weather source {name}");
+                        continue;
+                    };
+                    match wprovider_ref.refresh(source).await {
+                        Ok(TProvideOutcome::Provided) => println!("\tRefreshed successfully ws {name}"),
+                        Ok(TProvideOutcome::Skipped) => println!("\tRefresh of ws {name} skipped"),
+                        Err(e) => println!("\tError during refreshing weather source {name}: {e}"),
+                    }
+                },
+                TCommand::SetInterval(name, interval) => {
+                    let Some(sender) = intervals.get(name.as_str()) else {
+                        println!("\tInterval command for unknown weather source {name}");
+                        continue;
+                    };
+                    if sources.iter().any(|source| source.mqtt_topic_name == name && source.schedule.is_some()) {
+                        println!("\tWeather source {name} is provided by cron schedule, interval command is ignored");
+                        continue;
+                    }
+                    sender.send_replace(interval);
+                },
+            }
+        }
+    })
+}
+
+// Request interval is replaced by values received from `intervals`, e.g. by interval command
+pub fn start_task(wprovider_ref: Arc<TWeatherProvider>, ws: TWeatherSource,
+                  mut intervals: watch::Receiver<Duration>) {
+    println!("Starting task for weather source {} ...", ws.mqtt_topic_name);
+
+    task::spawn(async move {
+        println!("Done. Task for weather source {} started", ws.mqtt_topic_name);
+        let mut start = tokio::time::Instant::now();
+        if wprovider_ref.config.align_intervals && ws.schedule.is_none() {
+            let delay = schedule::align_delay(chrono::Utc::now(), ws.request_interval);
+            println!("\tFirst fetch of ws {} aligned to wall clock in {delay:?}", ws.mqtt_topic_name);
+            start += delay;
+        }
+        let mut period = ws.request_interval;
+        let mut interval = interval_at(start, period);
+        let mut failures = 0;
+        loop {
+            println!("\tWaiting... {}\n", ws.mqtt_topic_name);
+            match &ws.schedule {
+                Some(schedule) => {
+                    let Some(wait) = schedule.wait_from(chrono::Utc::now()) else {
+                        println!("\tSchedule '{schedule}' of ws {} never fires, task stopped", ws.mqtt_topic_name);
+                        return;
+                    };
+                    sleep(wait).await;
+                },
+                None => {
+                    tokio::select! {
+                        _ = interval.tick() => {},
+                        // sender may be dropped, then interval stays
+                        Ok(()) = intervals.changed() => {
+                            period = *intervals.borrow_and_update();
+                            println!("\tRequest interval of ws {} changed to {period:?}", ws.mqtt_topic_name);
+                            interval = interval_at(tokio::time::Instant::now() + period, period);
+                            continue;
+                        },
+                    }
+                },
+            }
+            // TODO: limit max time for loading and sending
+            println!("\tStart providing ws {} ... ", ws.mqtt_topic_name);
+            // failures are not fatal, circuit breaker pauses failing source
+            let succeeded = match wprovider_ref.provide(&ws).await {
+                Ok(TProvideOutcome::Provided) => {
+                    println!("\tProvided successfully ws {}", ws.mqtt_topic_name);
+                    true
+                },
+                // backoff stays as is until circuit lets fetches through
+                Ok(TProvideOutcome::Skipped) => continue,
+                Err(e) => {
+                    println!("\tError during providing weather source {}: {e}", ws.mqtt_topic_name);
+                    false
+                },
+            };
+            // cron sources keep their schedule
+            if ws.schedule.is_some() {
+                continue;
+            }
+            let backoff = failure_backoff(period, failures, wprovider_ref.config.failure_backoff_max_factor);
+            failures = if succeeded { 0 } else { failures.saturating_add(1) };
+            let next = failure_backoff(period, failures, wprovider_ref.config.failure_backoff_max_factor);
+            if next != backoff {
+                println!("\tNext fetch of ws {} in {next:?}", ws.mqtt_topic_name);
+                interval = interval_at(tokio::time::Instant::now() + next, next);
+            }
+        }
+    });
+}
+
+// Wait after consecutive failures of source: interval doubles with every failure up to max multiple of it
+fn failure_backoff(interval: Duration, failures: u32, max_factor: u32) -> Duration {
+    interval.saturating_mul(2u32.saturating_pow(failures).min(max_factor.max(1)))
+}
+
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type TPublished = Arc<Mutex<Vec<(String, String)>>>;
+
+    // Fake transmitter that records published full topics and payloads, first `failures` publishes fail
+    // or find full queue
+    struct TFakeTransmitter {
+        config: Config,
+        published: TPublished,
+        failures: Mutex<u32>,
+    }
+
+    impl Transmitter for TFakeTransmitter {
+        fn send_to_broker<'a>(&'a self, topic: &'a str, payload: String) -> TBoxFuture<'a, Result<(), ProviderError>> {
+            self.send_with_options(topic, payload, TPublishOptions::default())
+        }
+
+        fn send_with_options<'a>(&'a self, topic: &'a str, payload: String, options: TPublishOptions)
+                                 -> TBoxFuture<'a, Result<(), ProviderError>> {
+            Box::pin(async move {
+                let mut failures = self.failures.lock().unwrap();
+                if *failures > 0 {
+                    *failures -= 1;
+                    return Err(ProviderError::Mqtt("MQTT publish error: queue is full".to_string()));
+                }
+                let full_topic = TMQTTransmitter::make_full_topic(topic, &options.topic_suffix, &self.config);
+                self.published.lock().unwrap().push((full_topic, payload));
+                Ok(())
+            })
+        }
+
+        fn try_send_to_broker(&self, topic: &str, payload: &str, options: TPublishOptions)
+                              -> Result<bool, ProviderError> {
+            let mut failures = self.failures.lock().unwrap();
+            if *failures > 0 {
+                *failures -= 1;
+                return Ok(false);
+            }
+            let full_topic = TMQTTransmitter::make_full_topic(topic, &options.topic_suffix, &self.config);
+            self.published.lock().unwrap().push((full_topic, payload.to_string()));
+            Ok(true)
+        }
+
+        fn send_discovery<'a>(&'a self, object_id: &'a str, payload: String)
+                              -> TBoxFuture<'a, Result<(), ProviderError>> {
+            Box::pin(async move {
+                let full_topic = TMQTTransmitter::make_discovery_topic(object_id, &self.config);
+                self.published.lock().unwrap().push((full_topic, payload));
+                Ok(())
+            })
+        }
+    }
+
+    // Fake fetcher that returns fixture text for any URL except failing one, and "not modified" if requested
+    // with its etag
+    struct TFakeFetcher {
+        response: Result<String, String>,
+        etag: Option<String>,
+        failing_url: Option<&'static str>,
+    }
+
+    impl Fetcher for TFakeFetcher {
+        fn fetch<'a>(&'a self, url: &'a str, _headers: &'a [(String, String)], validators: &'a TCacheValidators)
+                     -> TBoxFuture<'a, Result<TFetchResult, ProviderError>> {
+            Box::pin(async move {
+                if self.failing_url == Some(url) {
+                    return Err(ProviderError::Http(format!("request error for {url}")));
+                }
+                if self.etag.is_some() && validators.etag == self.etag {
+                    return Ok(TFetchResult::NotModified);
+                }
+                let validators = TCacheValidators { etag: self.etag.clone(), last_modified: None };
+                self.response.clone().map(|body| TFetchResult::Modified { body: body.into_bytes(), validators })
+                             .map_err(ProviderError::Http)
+            })
+        }
+    }
+
+    #[tokio::test]
+    async fn test_exit_after_failures() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.exit_after_failures = 2;
+        let fetcher = TFakeFetcher { response: Err("HTTP status 503 error".to_string()), etag: None,
+                                     failing_url: None };
+        let wprovider = TWeatherProvider::new(Arc::new(config), Box::new(fetcher), Vec::new(),
+                                              Arc::new(TMetrics::new()));
+        let source = kp_inst_source();
+        let mut other_source = kp_inst_source();
+        other_source.mqtt_topic_name = "noaa_flux";
+        wprovider.track_failures([source.mqtt_topic_name, other_source.mqtt_topic_name].into_iter());
+        let all_failing = || wprovider.all_failing.notified();
+
+        for _ in 0..2 {
+            assert!(wprovider.provide(&source).await.is_err());
+        }
+        // other source hasn't failed yet
+        assert!(tokio::time::timeout(Duration::from_millis(10), all_failing()).await.is_err());
+        wprovider.record_outcome(&other_source, false);
+        wprovider.record_outcome(&other_source, false);
+        assert!(tokio::time::timeout(Duration::from_millis(10), all_failing()).await.is_ok());
+        // untracked source is ignored
+        let mut untracked = kp_inst_source();
+        untracked.mqtt_topic_name = "noaa_goes_mag";
+        wprovider.record_outcome(&untracked, true);
+        assert_eq!(wprovider.failure_streaks.lock().unwrap().len(), 2);
+    }
+
+    fn fake_provider(response: Result<String, String>) -> (TWeatherProvider, TPublished) {
+        fake_provider_with_failures(response, 0)
+    }
+
+    fn fake_provider_with_failures(response: Result<String, String>, failures: u32) -> (TWeatherProvider, TPublished) {
+        let (transmitter, published) = fake_transmitter(failures);
+        let wprovider = TWeatherProvider::new(Arc::new(Config::init_from_hashmap(&HashMap::new()).unwrap()),
+                                              Box::new(TFakeFetcher { response, etag: None, failing_url: None }),
+                                              vec![Box::new(transmitter)],
+                                              Arc::new(TMetrics::new()));
+        (wprovider, published)
+    }
+
+    fn fake_transmitter(failures: u32) -> (TFakeTransmitter, TPublished) {
+        let published = Arc::new(Mutex::new(Vec::new()));
+        let transmitter = TFakeTransmitter {
+            config: Config::init_from_hashmap(&HashMap::new()).unwrap(),
+            published: published.clone(),
+            failures: Mutex::new(failures),
+        };
+        (transmitter, published)
+    }
+
+    fn kp_inst_source() -> TWeatherSource {
+        TWeatherSource { source_url: "http://localhost/planetary_k_index_1m.json".to_string(),
+                         fallback_url: None,
+                         mqtt_topic_name: "noaa_kp_inst",
+                         request_interval: Duration::from_secs(300),
+                         schedule: None,
+                         convert: text_converter!(converter_kp_inst),
+                         options: ConvertOptions::default(),
+                         provide_options: TProvideOptions { value_field: "kp", ..Default::default() },
+                       }
+    }
+
+    #[test]
+    fn test_make_client_id() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert!(config.mqtt_clean_session);
+        assert_eq!(TMQTTransmitter::make_client_id("weather-provider", &config), "weather-provider-cubieboard");
+        config.mqtt_client_id = Some("staging-provider".to_string());
+        assert_eq!(TMQTTransmitter::make_client_id("weather-provider", &config), "staging-provider");
+    }
+
+    #[test]
+    fn test_complete_pubcomp_in_publish_order() {
+        let waiters = TPubCompWaiters::default();
+        let (first_tx, mut first_rx) = oneshot::channel();
+        let (second_tx, mut second_rx) = oneshot::channel();
+        waiters.lock().unwrap().extend([first_tx, second_tx]);
+        TMQTTransmitter::complete_pubcomp(&waiters, Ok(()));
+        assert_eq!(first_rx.try_recv(), Ok(Ok(())));
+        assert!(second_rx.try_recv().is_err());
+        TMQTTransmitter::complete_pubcomp(&waiters, Err("PUBCOMP reason PacketIdentifierNotFound".to_string()));
+        assert_eq!(second_rx.try_recv(), Ok(Err("PUBCOMP reason PacketIdentifierNotFound".to_string())));
+        // unexpected PUBCOMP is ignored
+        TMQTTransmitter::complete_pubcomp(&waiters, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_check_mqtt_connection_refused() {
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        // nothing listens on port 1
+        let result = check_mqtt_connection(&config, "127.0.0.1", 1).await;
+        assert!(matches!(result, Err(ProviderError::Mqtt(e)) if e.starts_with("I/O: ")));
+    }
+
+    #[test]
+    fn test_mqtt_credentials() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(config.mqtt_credentials(), None);
+        config.mqtt_password = Some(TSecret("s3cret".to_string()));
+        assert_eq!(config.mqtt_credentials(), None);
+        config.mqtt_username = Some("provider".to_string());
+        assert_eq!(config.mqtt_credentials(), Some(("provider".to_string(), "s3cret".to_string())));
+        assert!(!format!("{config:?}").contains("s3cret"));
+    }
+
+    #[test]
+    fn test_config_brokers() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(config.brokers(), Ok(vec![("localhost".to_string(), 1883)]));
+        config.mqtt_extra_brokers = Some("cloud:8883".to_string());
+        assert_eq!(config.brokers(), Ok(vec![("localhost".to_string(), 1883), ("cloud".to_string(), 8883)]));
+        config.mqtt_extra_brokers = Some("cloud".to_string());
+        assert!(config.brokers().is_err());
+    }
+
+    #[test]
+    fn test_connection_state_transitions() {
+        let mut state = TConnectionState::Disconnected;
+        let mut names = Vec::new();
+        for connected in [false, true, true, false, false, true] {
+            state = state.next(connected);
+            names.push(state.name());
+        }
+        assert_eq!(names, ["disconnected", "connected", "connected", "reconnecting", "reconnecting", "connected"]);
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(TMQTTransmitter::make_full_topic(CONNECTION_TOPIC, DEFAULT_TOPIC_SUFFIX, &config),
+                   "homeassistant/sensor/cubieboard_connection/state");
+    }
+
+    #[test]
+    fn test_failure_backoff() {
+        let interval = Duration::from_secs(60);
+        let waits: Vec<_> = (0..6).map(|failures| failure_backoff(interval, failures, 8).as_secs()).collect();
+        assert_eq!(waits, [60, 120, 240, 480, 480, 480]);
+        assert_eq!(failure_backoff(interval, u32::MAX, 8), Duration::from_secs(480));
+        // no backoff
+        assert_eq!(failure_backoff(interval, 3, 1), interval);
+        assert_eq!(failure_backoff(interval, 3, 0), interval);
+    }
+
+    #[test]
+    fn test_mqtt_transport() {
+        assert_eq!("wss".parse(), Ok(TMQTTTransport::Wss));
+        assert!("quic".parse::<TMQTTTransport>().is_err());
+        assert_eq!(TMQTTTransport::Tcp.url("localhost", 1883, "/mqtt"), None);
+        assert_eq!(TMQTTTransport::Ws.url("localhost", 8080, "/mqtt"), Some("ws://localhost:8080/mqtt".to_string()));
+        assert_eq!(TMQTTTransport::Wss.url("example.iot.amazonaws.com", 443, "mqtt"),
+                   Some("wss://example.iot.amazonaws.com:443/mqtt".to_string()));
+        let (address, transport) = TMQTTTransport::Tcp.broker("localhost", 1883, "/mqtt");
+        assert!(address == "localhost" && matches!(transport, rumqttc::Transport::Tcp));
+        let (address, transport) = TMQTTTransport::Ws.broker("localhost", 8080, "/mqtt");
+        assert!(address == "ws://localhost:8080/mqtt" && matches!(transport, rumqttc::Transport::Ws));
+        let (address, transport) = TMQTTTransport::Wss.broker("example.iot.amazonaws.com", 443, "/mqtt");
+        assert_eq!(address, "wss://example.iot.amazonaws.com:443/mqtt");
+        assert!(matches!(transport, rumqttc::Transport::Wss(_)));
+    }
+
+    #[tokio::test]
+    async fn test_check_mqtt_connection_ws() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let request = task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let len = stream.read(&mut buf).await.unwrap();
+            let _ = stream.write_all(b"HTTP/1.1 404 Not Found\r\nContent-Length: 0\r\n\r\n").await;
+            String::from_utf8_lossy(&buf[..len]).to_lowercase()
+        });
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.mqtt_transport = TMQTTTransport::Ws;
+        assert!(check_mqtt_connection(&config, "127.0.0.1", port).await.is_err());
+        // MQTT connection starts with WebSocket upgrade of MQTT_WS_PATH
+        let request = request.await.unwrap();
+        assert!(request.starts_with("get /mqtt http/1.1"), "{request}");
+        assert!(request.contains("upgrade: websocket") && request.contains("sec-websocket-protocol: mqtt"),
+                "{request}");
+    }
+
+    #[test]
+    fn test_reconnect_delay() {
+        let reconnect = (Duration::from_secs(1), Duration::from_secs(60));
+        let delays: Vec<_> = (0..8).map(|failures| reconnect_delay(failures, &reconnect).as_secs()).collect();
+        assert_eq!(delays, [1, 2, 4, 8, 16, 32, 60, 60]);
+        assert_eq!(reconnect_delay(u32::MAX, &reconnect), Duration::from_secs(60));
+        // zero delays don't make connection handler spin
+        assert_eq!(reconnect_delay(0, &(Duration::ZERO, Duration::ZERO)), MIN_RECONNECT_DELAY);
+        assert_eq!(reconnect_delay(3, &(Duration::from_secs(1), Duration::ZERO)), MIN_RECONNECT_DELAY);
+    }
+
+    #[test]
+    fn test_describe_connection_error() {
+        let e = rumqttc::ConnectionError::MqttState(rumqttc::StateError::AwaitPingResp);
+        assert_eq!(describe_connection_error(&e), "keep-alive timeout, no PINGRESP from broker");
+        let e = rumqttc::v5::ConnectionError::MqttState(rumqttc::v5::StateError::ServerDisconnect {
+            reason_code: rumqttc::v5::mqttbytes::v5::DisconnectReasonCode::ServerShuttingDown,
+            reason_string: Some("maintenance".to_string()),
+        });
+        assert_eq!(describe_connection_error_v5(&e), "broker disconnected with ServerShuttingDown (maintenance)");
+        assert_eq!(describe_connection_error(&rumqttc::ConnectionError::NetworkTimeout), "Network timeout");
+    }
+
+    #[test]
+    fn test_make_full_topic_state_base() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(TMQTTransmitter::make_full_topic("noaa_kp", "state", &config),
+                   "homeassistant/sensor/cubieboard_noaa_kp/state");
+        config.mqtt_state_base_topic = Some("space_weather".to_string());
+        assert_eq!(TMQTTransmitter::make_full_topic("noaa_kp_inst/attributes", "value", &config),
+                   "space_weather/cubieboard_noaa_kp_inst/attributes");
+        assert_eq!(TMQTTransmitter::make_full_topic("noaa_kp", "value", &config),
+                   "space_weather/cubieboard_noaa_kp/value");
+        assert_eq!(TMQTTransmitter::make_full_topic("noaa_kp", "", &config), "space_weather/cubieboard_noaa_kp");
+        assert_eq!(config.mqtt_base_topic, "homeassistant/sensor");
+    }
+
+    #[test]
+    fn test_make_full_topic_permutations() {
+        // (state base topic, device name, sensor name, suffix, full topic)
+        let cases = [
+            ("space_weather", "cubieboard", "noaa_kp", "state", "space_weather/cubieboard_noaa_kp/state"),
+            ("space_weather/", "cubieboard", "noaa_kp", "state", "space_weather/cubieboard_noaa_kp/state"),
+            ("space_weather//", "cubieboard", "noaa_kp", "", "space_weather/cubieboard_noaa_kp"),
+            ("home/space_weather", "cubieboard", "noaa_kp", "value", "home/space_weather/cubieboard_noaa_kp/value"),
+            ("/space_weather", "cubieboard", "noaa_kp", "state", "/space_weather/cubieboard_noaa_kp/state"),
+            ("", "cubieboard", "noaa_kp", "state", "cubieboard_noaa_kp/state"),
+            ("/", "cubieboard", "noaa_kp", "state", "cubieboard_noaa_kp/state"),
+            ("space_weather", "", "noaa_kp", "state", "space_weather/noaa_kp/state"),
+            ("space_weather", " ", "noaa_kp_inst/attributes", "state", "space_weather/noaa_kp_inst/attributes"),
+            ("space_weather", " attic ", "noaa_kp", "state", "space_weather/attic_noaa_kp/state"),
+            ("space_weather", "pi/attic", "noaa_kp", "state", "space_weather/pi_attic_noaa_kp/state"),
+            ("space_weather", "pi#1", "noaa_kp", "state", "space_weather/pi_1_noaa_kp/state"),
+            ("", "", "noaa_kp", "", "noaa_kp"),
+        ];
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        for (base_topic, device_name, sensor_name, suffix, expected) in cases {
+            config.mqtt_state_base_topic = Some(base_topic.to_string());
+            config.mqtt_device_name = device_name.to_string();
+            assert_eq!(TMQTTransmitter::make_full_topic(sensor_name, suffix, &config), expected,
+                       "base topic '{base_topic}', device name '{device_name}'");
+        }
+        config.mqtt_base_topic = "homeassistant/sensor/".to_string();
+        assert_eq!(TMQTTransmitter::make_discovery_topic("noaa_kp", &config), "homeassistant/sensor/noaa_kp/config");
+    }
+
+    #[test]
+    fn test_make_user_properties() {
+        let payload = r#"[{"time_tag":"00:00 01-05-2024","kp":3.0},{"time_tag":"03:00 01-05-2024","kp":2.67}]"#;
+        assert_eq!(TMQTTransmitter::make_user_properties("noaa_kp", payload),
+                   vec![("source".to_string(), "noaa_kp".to_string()),
+                        ("timestamp".to_string(), "03:00 01-05-2024".to_string())]);
+        assert_eq!(TMQTTransmitter::make_user_properties("kp_alert", "ON"),
+                   vec![("source".to_string(), "kp_alert".to_string())]);
+        assert_eq!("5".parse(), Ok(TMQTTProtocol::V5));
+        assert_eq!(Config::init_from_hashmap(&HashMap::new()).unwrap().mqtt_protocol, TMQTTProtocol::V311);
+    }
+
+    #[test]
+    fn test_duration() {
+        assert_eq!("45s".parse(), Ok(TDuration(Duration::from_secs(45))));
+        assert_eq!("6h".parse(), Ok(TDuration(Duration::from_secs(6 * 3600))));
+        assert_eq!(" 1h 30m ".parse(), Ok(TDuration(Duration::from_secs(5400))));
+        assert_eq!("500ms".parse(), Ok(TDuration(Duration::from_millis(500))));
+        assert_eq!("0".parse::<TDuration>().unwrap().enabled(), None);
+        assert_eq!("15m".parse::<TDuration>().unwrap().enabled(), Some(Duration::from_secs(900)));
+        assert!("600".parse::<TDuration>().is_err());
+        assert!("10 parsecs".parse::<TDuration>().is_err());
+    }
+
+    #[test]
+    fn test_resolve_seconds_aliases() {
+        let mut env = HashMap::from([("KP_INST_INTERVAL_S".to_string(), "90".to_string()),
+                                     ("HEARTBEAT_INTERVAL_S".to_string(), "30".to_string()),
+                                     ("HEARTBEAT_INTERVAL".to_string(), "1m".to_string())]);
+        assert_eq!(resolve_seconds_aliases(&mut env), Ok(()));
+        let config = Config::init_from_hashmap(&env).unwrap();
+        assert_eq!(config.kp_inst_interval, TDuration(Duration::from_secs(90)));
+        assert_eq!(config.heartbeat_interval, TDuration(Duration::from_secs(60)));
+        let mut env = HashMap::from([("ROLLING_WINDOW_S".to_string(), "1h".to_string())]);
+        assert_eq!(resolve_seconds_aliases(&mut env).unwrap_err(),
+                   "ROLLING_WINDOW_S must be number of seconds, got '1h', use ROLLING_WINDOW for durations");
+    }
+
+    #[test]
+    fn test_config_validate_intervals() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(config.validate(), Ok(()));
+        // rolling ranges are opt-in
+        assert_eq!(config.rolling_window.enabled(), None);
+        config.kp_release_interval = "0".parse().unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "KP_RELEASE_INTERVAL is 0ns, minimal request interval is 10s");
+        config.kp_release_interval = "10s".parse().unwrap();
+        config.kp_inst_interval = "9s".parse().unwrap();
+        assert!(config.validate().is_err());
+        config.kp_inst_interval = "10s".parse().unwrap();
+        config.combined_interval = "0".parse().unwrap();
+        assert_eq!(config.validate(), Ok(()));
+        config.combined_topic = Some("combined".to_string());
+        assert!(config.validate().is_err());
+        config.combined_topic = None;
+        config.http_connect_timeout = "2m".parse().unwrap();
+        assert_eq!(config.validate().unwrap_err().to_string(),
+                   "HTTP_CONNECT_TIMEOUT is 120s, it can't exceed HTTP_REQUEST_TIMEOUT 60s");
+        config.http_request_timeout = "0".parse().unwrap();
+        assert_eq!(config.validate(), Ok(()));
+    }
+
+    #[test]
+    fn test_fetcher_proxy_config() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.http_proxy = Some("http://proxy.local:3128".to_string());
+        config.http_no_proxy = Some("localhost,.lan".to_string());
+        assert!(TReqwestFetcher::new(&config).is_ok());
+        config.http_proxy = Some("not a proxy url".to_string());
+        assert!(TReqwestFetcher::new(&config).is_err());
+    }
+
+    // Serves single HTTP response on local port and returns its URL
+    async fn serve_once(response: impl Into<Vec<u8>>) -> String {
+        let response = response.into();
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        task::spawn(async move {
+            let (mut stream, _) = listener.accept().await.unwrap();
+            let mut buf = [0u8; 1024];
+            let _ = stream.read(&mut buf).await;
+            let _ = stream.write_all(&response).await;
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_http_status_error() {
+        let url = serve_once("HTTP/1.1 503 Service Unavailable\r\nContent-Length: 0\r\n\r\n").await;
+        let fetcher = TReqwestFetcher::new(&Config::init_from_hashmap(&HashMap::new()).unwrap()).unwrap();
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await;
+        assert!(matches!(result, Err(ProviderError::Http(e)) if e.starts_with("HTTP status 503 error")));
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_gzip() {
+        let body = include_str!("../tests/fixtures/planetary_k_index_1m.json");
+        let compressed = gzip(body.as_bytes());
+        let mut response = format!("HTTP/1.1 200 OK\r\nContent-Type: application/json\r\nContent-Encoding: gzip\r\n\
+                                    Content-Length: {}\r\n\r\n", compressed.len()).into_bytes();
+        response.extend(compressed);
+        let url = serve_once(response).await;
+        let fetcher = TReqwestFetcher::new(&Config::init_from_hashmap(&HashMap::new()).unwrap()).unwrap();
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await.unwrap();
+        assert!(matches!(result, TFetchResult::Modified { body: decoded, .. } if decoded == body.as_bytes()));
+    }
+
+    // Serves body with etag to every request, "not modified" if request has the etag
+    async fn serve_with_etag(body: &'static str, etag: &'static str) -> String {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        task::spawn(async move {
+            while let Ok((mut stream, _)) = listener.accept().await {
+                let mut buf = [0u8; 1024];
+                let size = stream.read(&mut buf).await.unwrap_or_default();
+                let request = String::from_utf8_lossy(&buf[..size]).to_lowercase();
+                let response = match request.contains(&format!("if-none-match: {etag}")) {
+                    true => format!("HTTP/1.1 304 Not Modified\r\nETag: {etag}\r\n\r\n"),
+                    false => format!("HTTP/1.1 200 OK\r\nETag: {etag}\r\nContent-Length: {}\r\n\r\n{body}",
+                                     body.len()),
+                };
+                let _ = stream.write_all(response.as_bytes()).await;
+            }
+        });
+        url
+    }
+
+    #[tokio::test]
+    async fn test_backfill_cache_validators() {
+        let url = serve_with_etag(include_str!("../tests/fixtures/noaa-planetary-k-index.json"), "\"v1\"").await;
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        let registry = converter_registry();
+        let env = |name: &str| ["SOURCE_NOAA_KP_URL", "SOURCE_NOAA_KP_HISTORY_URL"].contains(&name)
+                                   .then(|| url.clone());
+        let kp = builtin_source_configs(&config).into_iter().find(|source| source.name == "noaa_kp").unwrap()
+                                                 .build(env, None, &registry).unwrap();
+        let backfill = backfill_source_config(&config).build(env, None, &registry).unwrap();
+        assert_eq!(backfill.mqtt_topic_name, "noaa_kp_history");
+        let (mut wprovider, published) = fake_provider(Err("unused".to_string()));
+        wprovider.fetcher = Box::new(TReqwestFetcher::new(&config).unwrap());
+
+        wprovider.provide(&backfill).await.unwrap();
+        let backfilled = published.lock().unwrap().len();
+        assert_eq!(backfilled, config.kp_backfill_records);
+        assert!(published.lock().unwrap().iter().all(|(topic, _)| topic.contains("noaa_kp_history")));
+        // ETag of backfill isn't sent by noaa_kp
+        wprovider.provide(&kp).await.unwrap();
+        let provided = published.lock().unwrap().len();
+        assert!(provided > backfilled);
+        // own ETag of noaa_kp is
+        wprovider.provide(&kp).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), provided);
+        // backfill doesn't remember ETag
+        wprovider.provide(&backfill).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), provided + backfilled);
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_redirect() {
+        let html = serve_once("HTTP/1.1 200 OK\r\nContent-Type: text/html; charset=utf-8\r\nContent-Length: 13\r\n\r\n\
+                               <html></html>").await;
+        let url = serve_once(format!("HTTP/1.1 301 Moved Permanently\r\nLocation: {html}\r\n\
+                                      Content-Length: 0\r\n\r\n")).await;
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        let fetcher = TReqwestFetcher::new(&config).unwrap();
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await;
+        assert_eq!(result, Err(ProviderError::Http(format!("HTTP response of {html} after redirect is HTML page, \
+                                                            not source data"))));
+
+        config.http_max_redirects = 0;
+        let fetcher = TReqwestFetcher::new(&config).unwrap();
+        let url = serve_once("HTTP/1.1 302 Found\r\nLocation: /moved\r\nContent-Length: 0\r\n\r\n").await;
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await;
+        assert!(matches!(result, Err(ProviderError::Http(e)) if e.starts_with("HTTP redirect error")));
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_request_timeout() {
+        // connection is accepted, but response never comes
+        let listener = tokio::net::TcpListener::bind("127.0.0.1:0").await.unwrap();
+        let url = format!("http://{}/", listener.local_addr().unwrap());
+        task::spawn(async move {
+            let (_stream, _) = listener.accept().await.unwrap();
+            tokio::time::sleep(Duration::from_secs(5)).await;
+        });
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.http_request_timeout = TDuration(Duration::from_millis(100));
+        let fetcher = TReqwestFetcher::new(&config).unwrap();
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await;
+        assert!(matches!(result, Err(ProviderError::Http(e)) if e.starts_with("HTTP timeout error")));
+    }
+
+    #[test]
+    fn test_webhook_make_body() {
+        assert_eq!(TWebhookTransmitter::make_body("noaa_kp_inst", r#"{"kp":3.0}"#).to_string(),
+                   r#"{"payload":{"kp":3.0},"topic":"noaa_kp_inst"}"#);
+        assert_eq!(TWebhookTransmitter::make_body("kp_alert", "ON").to_string(),
+                   r#"{"payload":"ON","topic":"kp_alert"}"#);
+    }
+
+    #[tokio::test]
+    async fn test_webhook_http_status_error() {
+        let url = serve_once("HTTP/1.1 500 Internal Server Error\r\nContent-Length: 0\r\n\r\n").await;
+        let webhook = TWebhookTransmitter::new(url.clone()).unwrap();
+        let result = webhook.send_to_broker("noaa_kp", "3.0".to_string()).await;
+        assert!(matches!(result, Err(ProviderError::Http(e)) if e.starts_with(&format!("Webhook error ({url})"))));
+        let url = serve_once("HTTP/1.1 204 No Content\r\n\r\n").await;
+        let webhook = TWebhookTransmitter::new(url).unwrap();
+        assert_eq!(webhook.send_to_broker("noaa_kp", "3.0".to_string()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_influx_write_error() {
+        let url = serve_once("HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n").await;
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        let influx = TInfluxTransmitter::new(&config, url.clone()).unwrap();
+        let records = converter_kp_inst(include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string(),
+                                        &ConvertOptions::default()).unwrap().records;
+        let result = influx.send_records("noaa_kp_inst", &records).await;
+        assert!(result.unwrap_err().to_string().starts_with(&format!("InfluxDB write error ({url})")));
+        // nothing to write
+        assert_eq!(influx.send_records("noaa_sw_forecast", &[]).await, Ok(()));
+        assert_eq!(influx.send_to_broker("kp_alert", "ON".to_string()).await, Ok(()));
+    }
+
+    #[tokio::test]
+    async fn test_provide_influx_records() {
+        let url = serve_once("HTTP/1.1 401 Unauthorized\r\nContent-Length: 0\r\n\r\n").await;
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Ok(raw_data));
+        wprovider.transmitters.push(Box::new(TInfluxTransmitter::new(&config, url.clone()).unwrap()));
+        // payloads are published, records of source are written to InfluxDB
+        let result = wprovider.provide(&kp_inst_source()).await;
+        assert!(result.unwrap_err().to_string().contains(&format!("InfluxDB write error ({url})")));
+        assert_eq!(published.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_fetcher_binary_body() {
+        let url = serve_once("HTTP/1.1 200 OK\r\nContent-Length: 4\r\n\r\n\u{0}\u{1}\u{2}\u{3}").await;
+        let fetcher = TReqwestFetcher::new(&Config::init_from_hashmap(&HashMap::new()).unwrap()).unwrap();
+        let result = fetcher.fetch(&url, &[], &TCacheValidators::default()).await;
+        assert_eq!(result, Ok(TFetchResult::Modified { body: vec![0, 1, 2, 3],
+                                                        validators: TCacheValidators::default() }));
+    }
+
+    #[test]
+    fn test_converter_body_type() {
+        let bytes_len: TconvertBytesFn = |body, _| {
+            Ok(Converted { payloads: vec![("".to_string(), body.len().to_string())], records: Vec::new() })
+        };
+        let options = ConvertOptions::default();
+        assert_eq!(TConverter::Bytes("bytes_len", bytes_len).convert(vec![0xff, 0xfe], &options).unwrap().payloads,
+                   vec![("".to_string(), "2".to_string())]);
+        let text_result = text_converter!(converter_kp).convert(vec![0xff, 0xfe], &options);
+        assert!(matches!(text_result, Err(ConvertError::Deserialize(e)) if e.starts_with("body is not UTF-8")));
+    }
+
+    #[test]
+    fn test_self_test_source() {
+        assert_eq!(self_test_source(&kp_inst_source()), Ok(2));
+        let mut source = kp_inst_source();
+        source.mqtt_topic_name = "noaa_solar_wind";
+        assert_eq!(self_test_source(&source), Err("no bundled sample payload".to_string()));
+    }
+
+    #[test]
+    fn test_describe_source() {
+        assert_eq!(describe_source(&kp_inst_source()),
+                   "noaa_kp_inst\n\turl: http://localhost/planetary_k_index_1m.json\n\tinterval: 300s\n\t\
+                    converter: converter_kp_inst");
+    }
+
+    #[test]
+    fn test_heartbeat_payload() {
+        let now = chrono::TimeZone::with_ymd_and_hms(&chrono::Utc, 2024, 5, 1, 10, 3, 30).unwrap();
+        assert_eq!(heartbeat_payload(7, now), r#"{"counter":7,"timestamp":"2024-05-01T10:03:30Z"}"#);
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        assert_eq!(TMQTTransmitter::make_full_topic(HEARTBEAT_TOPIC, DEFAULT_TOPIC_SUFFIX, &config),
+                   "homeassistant/sensor/cubieboard_heartbeat/state");
+    }
+
+    #[test]
+    fn test_compress_payload() {
+        use base64::Engine;
+        let (topic, payload) = compress_payload("noaa_sw_forecast".to_string(), "state", r#"{"kp":[]}"#);
+        assert_eq!(topic, "noaa_sw_forecast/state.gz.b64");
+        let compressed = base64::engine::general_purpose::STANDARD.decode(payload).unwrap();
+        let mut decoded = String::new();
+        std::io::Read::read_to_string(&mut flate2::read::GzDecoder::new(compressed.as_slice()), &mut decoded).unwrap();
+        assert_eq!(decoded, r#"{"kp":[]}"#);
+        let (topic, _) = compress_payload("noaa_kp_inst/attributes".to_string(), "state", "{}");
+        assert_eq!(topic, "noaa_kp_inst/attributes.gz.b64");
+        assert_eq!(compress_payload("noaa_sw_forecast".to_string(), "", "{}").0, "noaa_sw_forecast.gz.b64");
+    }
+
+    #[test]
+    fn test_check_payload_size() {
+        assert_eq!(check_payload_size("noaa_sw_forecast", "12345", None), None);
+        assert_eq!(check_payload_size("noaa_sw_forecast", "12345", Some(5)), None);
+        assert_eq!(check_payload_size("noaa_sw_forecast", "123456", Some(5)),
+                   Some("payload of noaa_sw_forecast is 6 bytes, limit is 5 bytes".to_string()));
+    }
+
+    #[test]
+    fn test_parse_headers() {
+        let env = |name: &str| (name == "API_KEY").then(|| "secret".to_string());
+        assert_eq!(parse_headers("X-Api-Key: ${API_KEY}; Accept: text/plain ;", env),
+                   Ok(vec![("X-Api-Key".to_string(), "secret".to_string()),
+                           ("Accept".to_string(), "text/plain".to_string())]));
+        assert_eq!(parse_headers("Authorization: Bearer ${API_KEY}-${API_KEY}", env),
+                   Ok(vec![("Authorization".to_string(), "Bearer secret-secret".to_string())]));
+        assert!(parse_headers("X-Api-Key: ${MISSING}", env).is_err());
+        assert!(parse_headers("X-Api-Key", env).is_err());
+        assert_eq!(source_env_name("noaa_kp", "HEADERS"), "SOURCE_NOAA_KP_HEADERS");
+    }
+
+    #[test]
+    fn test_source_config_build() {
+        let registry = converter_registry();
+        let source_config = || TSourceConfig {
+            qos: QoS::ExactlyOnce,
+            ..TSourceConfig::new("noaa_kp", "http://localhost/kp.json".to_string(), Duration::from_secs(300),
+                                 "converter_kp")
+        };
+        let source = source_config().build(|_| None, Some(1024), &registry).unwrap();
+        assert_eq!(source.source_url, "http://localhost/kp.json");
+        assert_eq!(source.provide_options.publish, TPublishOptions { qos: QoS::ExactlyOnce, retain: false,
+                                                                     topic_suffix: "state".to_string() });
+        assert_eq!(source.provide_options.max_payload_size, Some(1024));
+
+        let env = HashMap::from([("SOURCE_NOAA_KP_QOS", "0"), ("SOURCE_NOAA_KP_RETAIN", "true"),
+                                 ("SOURCE_NOAA_KP_URL", "http://mirror/kp.json"),
+                                 ("SOURCE_NOAA_KP_MAX_PAYLOAD_SIZE", "2048"), ("SOURCE_NOAA_KP_COMPRESS", "gzip"),
+                                 ("SOURCE_NOAA_KP_TOPIC_SUFFIX", "value")]);
+        let source = source_config().build(|name| env.get(name).map(|value| value.to_string()), None, &registry)
+                                    .unwrap();
+        assert_eq!(source.source_url, "http://mirror/kp.json");
+        assert_eq!(source.provide_options.publish, TPublishOptions { qos: QoS::AtMostOnce, retain: true,
+                                                                     topic_suffix: "value".to_string() });
+        assert_eq!(source.provide_options.max_payload_size, Some(2048));
+        assert!(source.provide_options.compress);
+
+        assert_eq!(source.schedule, None);
+        let cron = |name: &str| (name == "SOURCE_NOAA_KP_CRON").then(|| "2,32 * * * *".to_string());
+        let source = source_config().build(cron, None, &registry).unwrap();
+        assert_eq!(source.schedule, Some("2,32 * * * *".parse().unwrap()));
+        assert!(describe_source(&source).contains("\n\tschedule: 2,32 * * * * (UTC)\n"));
+
+        assert_eq!(source.provide_options.min_publish_interval, None);
+        let debounce = |name: &str| (name == "SOURCE_NOAA_KP_MIN_PUBLISH_INTERVAL").then(|| "5m".to_string());
+        let source = source_config().build(debounce, None, &registry).unwrap();
+        assert_eq!(source.provide_options.min_publish_interval, Some(Duration::from_secs(300)));
+
+        let wrong_suffix = |name: &str| (name == "SOURCE_NOAA_KP_TOPIC_SUFFIX").then(|| "state/#".to_string());
+        assert_eq!(source_config().build(wrong_suffix, None, &registry).err(),
+                   Some(ProviderError::Config("wrong TOPIC_SUFFIX: 'state/#' isn't topic leaf".to_string())));
+
+        let wrong_qos = |name: &str| (name == "SOURCE_NOAA_KP_QOS").then(|| "3".to_string());
+        assert_eq!(source_config().build(wrong_qos, None, &registry).err(),
+                   Some(ProviderError::Config("wrong QoS '3', expected 0, 1 or 2".to_string())));
+    }
+
+    #[test]
+    fn test_flux_unit_source_config() {
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.flux_unit = Some(FluxUnit::PerM2);
+        let flux = builtin_source_configs(&config).into_iter().find(|source| source.name == "noaa_flux").unwrap();
+        assert_eq!(flux.ha_sensor.unwrap().unit, Some("m-2 s-1 sr-1"));
+        let (_, to_level) = flux.provide_options.scale.unwrap();
+        assert_eq!((to_level(9e4), to_level(1e5)), (0, 1));
+    }
+
+    #[test]
+    fn test_source_config_converter() {
+        let registry = converter_registry();
+        assert_eq!(registry.len(), 9);
+        let source_config = || TSourceConfig::new("noaa_kp", "http://localhost/kp.json".to_string(),
+                                                  Duration::from_secs(300), "converter_kp");
+        assert_eq!(source_config().build(|_| None, None, &registry).unwrap().convert.name(), "converter_kp");
+        let history = |name: &str| (name == "SOURCE_NOAA_KP_CONVERTER").then(|| "converter_kp_history".to_string());
+        assert_eq!(source_config().build(history, None, &registry).unwrap().convert.name(), "converter_kp_history");
+        let unknown = |name: &str| (name == "SOURCE_NOAA_KP_CONVERTER").then(|| "converter_dst".to_string());
+        assert_eq!(source_config().build(unknown, None, &registry).err(),
+                   Some(ProviderError::Config("unknown converter 'converter_dst'".to_string())));
+    }
+
+    #[tokio::test]
+    async fn test_provide_source() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+
+        let published = published.lock().unwrap();
+        assert_eq!(*published, vec![("homeassistant/sensor/cubieboard_noaa_kp_inst/state".to_string(),
+                                     "{\"time_tag\":\"00:29 01-05-2024\",\"kp\":4.0}".to_string()),
+                                    ("homeassistant/sensor/cubieboard_noaa_kp_inst/attributes".to_string(),
+                                     "{\"time_tag\":\"00:29 01-05-2024\",\"g_scale\":0}".to_string())]);
+        let latest: serde_json::Value = serde_json::from_str(&wprovider.metrics.render_json()).unwrap();
+        assert_eq!(latest["sources"][0]["value"].to_string(), "{\"kp\":4.0,\"time_tag\":\"00:29 01-05-2024\"}");
+    }
+
+    #[tokio::test]
+    async fn test_provide_topic_suffix() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        let mut source = kp_inst_source();
+        source.provide_options.publish.topic_suffix = String::new();
+        wprovider.provide(&source).await.unwrap();
+
+        let published = published.lock().unwrap();
+        assert_eq!(published[0].0, "homeassistant/sensor/cubieboard_noaa_kp_inst");
+        assert_eq!(published[1].0, "homeassistant/sensor/cubieboard_noaa_kp_inst/attributes");
+    }
+
+    #[tokio::test]
+    async fn test_provide_combined() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Ok(raw_data));
+        let mut config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        config.combined_topic = Some("combined".to_string());
+        wprovider.config = Arc::new(config);
+        assert_eq!(wprovider.take_combined(), None);
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+        assert!(published.lock().unwrap().is_empty());
+
+        assert_eq!(wprovider.take_combined().unwrap(),
+                   "{\"noaa_kp_inst\":{\"kp\":4.0,\"time_tag\":\"00:29 01-05-2024\"},\
+                    \"noaa_kp_inst/attributes\":{\"g_scale\":0,\"time_tag\":\"00:29 01-05-2024\"}}");
+        // nothing changed since previous document
+        assert_eq!(wprovider.take_combined(), None);
+    }
+
+    #[tokio::test]
+    async fn test_provide_smoothed_source() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        let mut source = kp_inst_source();
+        source.options.payload_format = PayloadFormat::Scalar;
+        source.provide_options.ema_alpha = Some(0.5);
+        wprovider.ema_state.lock().unwrap().insert("noaa_kp_inst".to_string(), 3.0);
+        wprovider.provide(&source).await.unwrap();
+
+        let published = published.lock().unwrap();
+        assert_eq!(published[0], ("homeassistant/sensor/cubieboard_noaa_kp_inst_raw/state".to_string(), "4.0".to_string()));
+        assert_eq!(published[1], ("homeassistant/sensor/cubieboard_noaa_kp_inst/state".to_string(), "3.5".to_string()));
+    }
+
+    #[test]
+    fn test_update_rolling() {
+        let (wprovider, _) = fake_provider(Err("unused".to_string()));
+        let mut source = kp_inst_source();
+        let start = Instant::now();
+        assert_eq!(wprovider.update_rolling(&source, 3.0, start), None);
+        source.provide_options.rolling_window = Some(Duration::from_secs(3600));
+        let update = |value: f32, minutes: u64| {
+            wprovider.update_rolling(&source, value, start + Duration::from_secs(minutes * 60)).unwrap()
+        };
+        assert_eq!(update(3.0, 0), r#"{"min":3.0,"max":3.0,"samples":1}"#);
+        assert_eq!(update(5.33, 30), r#"{"min":3.0,"max":5.33,"samples":2}"#);
+        assert_eq!(update(4.0, 60), r#"{"min":3.0,"max":5.33,"samples":3}"#);
+        // the first value is out of window
+        assert_eq!(update(4.67, 61), r#"{"min":4.0,"max":5.33,"samples":3}"#);
+    }
+
+    #[tokio::test]
+    async fn test_provide_rolling_range() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        let mut source = kp_inst_source();
+        source.provide_options.rolling_window = Some(Duration::from_secs(3600));
+        wprovider.provide(&source).await.unwrap();
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.last().unwrap(), &("homeassistant/sensor/cubieboard_noaa_kp_inst_range/state".to_string(),
+                                                r#"{"min":4.0,"max":4.0,"samples":1}"#.to_string()));
+    }
+
+    #[test]
+    fn test_check_alert_hysteresis() {
+        let (wprovider, _) = fake_provider(Err("unused".to_string()));
+        let mut source = kp_inst_source();
+        source.provide_options.alert = Some(TAlertOptions { topic: "kp_alert", threshold: 5.0, hysteresis: 0.5 });
+        let check = |kp: f32| wprovider.check_alert(&source, kp);
+        assert_eq!(check(4.67), None);
+        assert_eq!(check(5.33), Some(("kp_alert", "ON".to_string())));
+        assert_eq!(check(6.0), None);
+        assert_eq!(check(4.67), None);
+        assert_eq!(check(4.33), Some(("kp_alert", "OFF".to_string())));
+        assert_eq!(check(3.0), None);
+    }
+
+    #[tokio::test]
+    async fn test_provide_summary() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        let mut source = kp_inst_source();
+        source.provide_options.scale = Some(('G', scales::kp_to_g_scale));
+        wprovider.scale_levels.lock().unwrap().insert('S', 2);
+        wprovider.provide(&source).await.unwrap();
+
+        let published = published.lock().unwrap();
+        assert_eq!(published.last().unwrap(),
+                   &("homeassistant/sensor/cubieboard_space_weather_summary/state".to_string(),
+                     "{\"g_scale\":0,\"s_scale\":2,\"severity\":\"Storm\"}".to_string()));
+    }
+
+    #[tokio::test]
+    async fn test_announce() {
+        let (wprovider, published) = fake_provider(Err("unused".to_string()));
+        let mut source = kp_inst_source();
+        wprovider.announce(&[source.clone()]).await.unwrap();
+        // source without sensor isn't announced
+        assert!(published.lock().unwrap().is_empty());
+        source.provide_options.ha_sensor = Some(THASensor { name: "Estimated Kp index", ..Default::default() });
+        source.provide_options.has_attributes = true;
+        wprovider.announce(&[source]).await.unwrap();
+        let published = published.lock().unwrap();
+        assert_eq!(published.iter().map(|(topic, _)| topic.as_str()).collect::<Vec<_>>(),
+                   ["homeassistant/sensor/cubieboard_noaa_kp_inst/config",
+                    "homeassistant/sensor/cubieboard_noaa_kp_inst_time/config"]);
+        let config: serde_json::Value = serde_json::from_str(&published[0].1).unwrap();
+        assert_eq!(config["state_topic"], "homeassistant/sensor/cubieboard_noaa_kp_inst/state");
+        assert_eq!(config["json_attributes_topic"], "homeassistant/sensor/cubieboard_noaa_kp_inst/attributes");
+    }
+
+    #[tokio::test]
+    async fn test_provide_fallback_url() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Err("unused".to_string()));
+        wprovider.fetcher = Box::new(TFakeFetcher { response: Ok(raw_data), etag: None,
+                                                    failing_url: Some("http://localhost/planetary_k_index_1m.json") });
+        // no fallback
+        let result = wprovider.provide(&kp_inst_source()).await;
+        let request_error = "request error for http://localhost/planetary_k_index_1m.json";
+        assert_eq!(result, Err(ProviderError::Http(request_error.to_string())));
+        let mut source = kp_inst_source();
+        source.fallback_url = Some("http://mirror/planetary_k_index_1m.json".to_string());
+        wprovider.provide(&source).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 2);
+        // both fail
+        source.fallback_url = Some("http://localhost/planetary_k_index_1m.json".to_string());
+        let result = wprovider.provide(&source).await;
+        assert_eq!(result, Err(ProviderError::Http(format!("{request_error}; fallback: {request_error}"))));
+    }
+
+    #[tokio::test]
+    async fn test_provide_not_modified() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Err("unused".to_string()));
+        wprovider.fetcher = Box::new(TFakeFetcher { response: Ok(raw_data), etag: Some("\"v1\"".to_string()),
+                                                    failing_url: None });
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 2);
+        // second fetch is answered with 304, nothing new is published
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 2);
+    }
+
+    #[test]
+    fn test_parse_command() {
+        assert_eq!(TCommand::parse("refresh", "noaa_kp_inst\n"), Ok(TCommand::Refresh("noaa_kp_inst".to_string())));
+        assert_eq!(TCommand::parse("interval", " noaa_kp_inst 1m "),
+                   Ok(TCommand::SetInterval("noaa_kp_inst".to_string(), Duration::from_secs(60))));
+        assert!(TCommand::parse("interval", "noaa_kp_inst").is_err());
+        assert!(TCommand::parse("interval", "noaa_kp_inst 0").is_err());
+        assert_eq!(TCommand::parse("interval", "noaa_kp 1ms"),
+                   Err("noaa_kp is 1ms, minimal request interval is 10s".to_string()));
+        assert!(TCommand::parse("interval", "noaa_kp_inst fast").is_err());
+        assert!(TCommand::parse("restart", "").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_command_task() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Err("unused".to_string()));
+        wprovider.fetcher = Box::new(TFakeFetcher { response: Ok(raw_data), etag: Some("\"v1\"".to_string()),
+                                                    failing_url: None });
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+        let (interval, interval_receiver) = watch::channel(Duration::from_secs(300));
+        let (sender, receiver) = mpsc::unbounded_channel();
+        let task = start_command_task(Arc::new(wprovider), vec![kp_inst_source()],
+                                      HashMap::from([("noaa_kp_inst", interval)]), receiver);
+
+        let config = Config::init_from_hashmap(&HashMap::new()).unwrap();
+        let prefix = TMQTTransmitter::make_full_topic(COMMAND_TOPIC_PREFIX, DEFAULT_TOPIC_SUFFIX, &config);
+        assert_eq!(prefix, "homeassistant/sensor/cubieboard_command/");
+        let commands: TCommandChannel = Some((prefix.clone(), sender));
+        TMQTTransmitter::forward_command(&commands, "homeassistant/sensor/other", b"noaa_kp_inst");
+        TMQTTransmitter::forward_command(&commands, &(prefix.clone() + "refresh"), b"noaa_unknown");
+        // refresh ignores "not modified" of cached data
+        TMQTTransmitter::forward_command(&commands, &(prefix.clone() + "refresh"), b"noaa_kp_inst\n");
+        TMQTTransmitter::forward_command(&commands, &(prefix.clone() + "interval"), b"noaa_kp_inst 1m");
+        TMQTTransmitter::forward_command(&commands, &(prefix + "interval"), b"noaa_unknown 1m");
+        drop(commands);
+        task.await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 4);
+        assert_eq!(*interval_receiver.borrow(), Duration::from_secs(60));
+    }
+
+    #[tokio::test]
+    async fn test_provide_circuit_breaker() {
+        let (wprovider, _) = fake_provider(Err("HTTP status 503 error".to_string()));
+        // default threshold is 5 failures
+        for _ in 0..5 {
+            assert!(wprovider.provide(&kp_inst_source()).await.is_err());
+        }
+        assert_eq!(wprovider.provide(&kp_inst_source()).await, Ok(TProvideOutcome::Skipped));
+        assert!(wprovider.breakers.lock().unwrap()["noaa_kp_inst"].is_open());
+    }
+
+    #[tokio::test]
+    async fn test_provide_half_open_probe_error() {
+        // converter error isn't source fault, but it still settles half-open probe
+        let (wprovider, _) = fake_provider(Ok("[]".to_string()));
+        let mut source = kp_inst_source();
+        source.convert = TConverter::Text("converter_serialize_error", |_, _| {
+            Err(ConvertError::Serialize("key must be a string".to_string()))
+        });
+        let mut breaker = TCircuitBreaker::new(1, Duration::ZERO);
+        breaker.record_failure(Instant::now());
+        wprovider.breakers.lock().unwrap().insert("noaa_kp_inst".to_string(), breaker);
+        assert!(wprovider.provide(&source).await.is_err());
+        assert!(wprovider.breakers.lock().unwrap()["noaa_kp_inst"].is_open());
+        // zero cooldown, next probe is allowed
+        assert!(wprovider.provide(&source).await.is_err());
+    }
+
+    #[tokio::test]
+    async fn test_provide_publish_retry() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider_with_failures(Ok(raw_data), 1);
+        wprovider.provide(&kp_inst_source()).await.unwrap();
+        assert_eq!(published.lock().unwrap().len(), 2);
+    }
+
+    #[tokio::test]
+    async fn test_provide_publish_error() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider_with_failures(Ok(raw_data), PUBLISH_ATTEMPTS);
+        let result = wprovider.provide(&kp_inst_source()).await;
+        assert_eq!(result, Err(ProviderError::Mqtt("MQTT publish error: queue is full".to_string())));
+        // failed state doesn't prevent publishing of attributes
+        assert_eq!(published.lock().unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_send_latest_drops_oldest() {
+        let (wprovider, published) = fake_provider_with_failures(Err("unused".to_string()), 1);
+        // queue is full, payload waits in buffer
+        assert_eq!(wprovider.send_latest("noaa_kp_inst", "1".to_string(), TPublishOptions::default()), Ok(()));
+        assert!(published.lock().unwrap().is_empty());
+        // newer payload replaces unsent one
+        assert_eq!(wprovider.send_latest("noaa_kp_inst", "2".to_string(), TPublishOptions::default()), Ok(()));
+        assert_eq!(*published.lock().unwrap(),
+                   vec![("homeassistant/sensor/cubieboard_noaa_kp_inst/state".to_string(), "2".to_string())]);
+        assert!(wprovider.unsent.lock().unwrap().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_provide_multiple_brokers() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (mut wprovider, published) = fake_provider(Ok(raw_data));
+        let (failing, failing_published) = fake_transmitter(u32::MAX);
+        let (second, second_published) = fake_transmitter(0);
+        wprovider.transmitters.insert(0, Box::new(failing));
+        wprovider.transmitters.push(Box::new(second));
+        let result = wprovider.provide(&kp_inst_source()).await;
+        // failing broker doesn't block others, its error is reported per payload
+        let error = ProviderError::Mqtt("MQTT publish error: queue is full".to_string());
+        assert_eq!(result, Err(ProviderError::Multiple(vec![error.clone(), error])));
+        assert!(failing_published.lock().unwrap().is_empty());
+        assert_eq!(published.lock().unwrap().len(), 2);
+        assert_eq!(*second_published.lock().unwrap(), *published.lock().unwrap());
+    }
+
+    #[test]
+    fn test_parse_brokers() {
+        assert_eq!(parse_brokers("localhost:1883, cloud.example.com:8883"),
+                   Ok(vec![("localhost".to_string(), 1883), ("cloud.example.com".to_string(), 8883)]));
+        assert!(parse_brokers("localhost").is_err());
+        assert!(parse_brokers("localhost:port").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_provide_min_publish_interval() {
+        let raw_data = include_str!("../tests/fixtures/planetary_k_index_1m.json").to_string();
+        let (wprovider, published) = fake_provider(Ok(raw_data));
+        let mut source = kp_inst_source();
+        source.provide_options.min_publish_interval = Some(Duration::from_secs(60));
+        wprovider.provide(&source).await.unwrap();
+        wprovider.provide(&source).await.unwrap();
+        // state and attributes are published once
+        assert_eq!(published.lock().unwrap().len(), 2);
+
+        let now = Instant::now();
+        assert!(!wprovider.publish_due("noaa_kp_inst", source.provide_options.min_publish_interval, now));
+        assert!(wprovider.publish_due("noaa_kp_inst", source.provide_options.min_publish_interval,
+                                      now + Duration::from_secs(60)));
+        assert!(wprovider.publish_due("noaa_kp_inst", None, now));
+        assert!(wprovider.publish_due("noaa_flux", source.provide_options.min_publish_interval, now));
+    }
+
+    #[tokio::test]
+    async fn test_provide_fetch_error() {
+        let (wprovider, published) = fake_provider(Err("HTTP reqwest error: timeout".to_string()));
+        let result = wprovider.provide(&kp_inst_source()).await;
+        assert_eq!(result, Err(ProviderError::Http("HTTP reqwest error: timeout".to_string())));
+        assert!(published.lock().unwrap().is_empty());
+    }
+}
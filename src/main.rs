@@ -1,193 +1,219 @@
-extern crate chrono;
-extern crate rumqttc;
-extern crate envconfig;
-
-pub mod parsers;
-pub mod converters;
-
-use reqwest::Error;
-use tokio::task;
-use tokio::time::{Duration, interval};
-use std::sync::{Arc, Mutex};
+use std::collections::HashMap;
+use std::sync::Arc;
 use envconfig::Envconfig;
-use rumqttc::{MqttOptions, Client, QoS};
-use converters::*;
+use weather_provider::*;
+use weather_provider::error::ProviderError;
+use weather_provider::metrics::{self, TMetrics};
 
 
-type TconvertFn = fn(String) -> Result::<String, String>;
+const VERSION: &str = env!("CARGO_PKG_VERSION");
+const GIT_COMMIT: &str = env!("GIT_COMMIT");
 
-#[derive(Clone)]
-struct TWeatherSource {
-    source_url: &'static str,
-    mqtt_topic_name: &'static str,
-    request_interval_s: u16,
-    convert: TconvertFn
+// Value following flag in command line, e.g. "noaa_flux" for `--source noaa_flux`
+fn arg_value(args: impl Iterator<Item = String>, flag: &str) -> Option<String> {
+    let mut args = args.skip_while(|arg| arg != flag);
+    args.next()?;
+    args.next()
 }
 
-struct TWeatherProvider {
-    transmitter: TMQTTransmitter,
+fn build_info() -> String {
+    format!("weather-provider {VERSION} (commit {GIT_COMMIT})")
 }
 
-impl TWeatherProvider {
-    async fn provide(&self, source: &TWeatherSource) -> Result::<(), String> {
-        println!("\tProviding weather source {}", source.mqtt_topic_name);
-        let raw_data = self.load_text(source).await.map_err(|e: Error| format!("HTTP reqwest error: {e}"))?;
-        let payload = (source.convert)(raw_data)?;
-        self.send(source, payload).await
-    }
-    async fn send(&self, source: &TWeatherSource, payload: String) -> Result::<(), String> {
-        self.transmitter.send_to_broker(source.mqtt_topic_name, payload).await
-    }
-    async fn load_text(&self, source: &TWeatherSource) -> Result::<String, Error> {
-        reqwest::get(source.source_url).await?    // make GET request
-                .error_for_status()?    // handling HTTP status
-                .text().await
+#[tokio::main]
+async fn main() {
+    if std::env::args().any(|arg| arg == "--version") {
+        println!("{}", build_info());
+        return;
     }
-}
+    println!("Starting {}", build_info());
 
+    // <NAME>_FILE env vars of secrets are resolved, so secrets may come from files
+    let mut env = secret::resolve_secret_files(std::env::vars(), |path| std::fs::read_to_string(path))
+        .unwrap_or_else(|e| panic!("Wrong config: {e}"));
+    resolve_seconds_aliases(&mut env).unwrap_or_else(|e| panic!("Wrong config: {e}"));
+    // immutable, all time live, multithreading read access
+    let config = Config::init_from_hashmap(&env).unwrap();
+    if let Err(e) = config.validate() {
+        panic!("Wrong config: {e}");
+    }
 
-struct TMQTTSettings {
-    name: &'static str,
-    config: Arc<Config>,
-}
+    println!("Using config:\n{:?}", config);
 
-struct TMQTTransmitter {
-    settings: TMQTTSettings,
-    client: Arc<Mutex<Client>>,
-}
+    let source_configs = builtin_source_configs(&config);
+    let backfill_config = backfill_source_config(&config);
+    let kp_backfill = config.kp_backfill;
 
-impl TMQTTransmitter {
-    fn new(settings: TMQTTSettings) -> Result<(Self, task::JoinHandle<()>), String> {
-        let mut mqttoptions = MqttOptions::new(settings.name, &settings.config.mqtt_host, settings.config.mqtt_port);
-        mqttoptions.set_keep_alive(Duration::from_secs(settings.config.mqtt_keep_alive.into()));
-        println!("Connecting to MQTT broker...");
-        let (client, mut connection) = Client::new(mqttoptions, 10);
-
-        let transmitter = Self { settings: settings, client: Arc::new(Mutex::new(client))};
-
-        println!("Spawn Connection handler thread");
-        // Connection handler thread
-        let handler = task::spawn_blocking( move || {
-            println!("Connection handler thread spawned");
-            loop {
-                // The `EventLoop`/`Connection` must be regularly polled(`.next()` in case of `Connection`) in order
-                // to send, receive and process packets from the broker, i.e. move ahead.
-                for (_, notification) in connection.iter().enumerate() {
-                    if notification.is_err() {
-                        // just print
-                        let _ = notification.inspect_err(|e| println!("MQTT connection error: {e}"));
-                    }
-                }
-            }
-        });
+    let registry = converter_registry();
+    let build_source = |source_config: TSourceConfig| {
+        let name = source_config.name;
+        source_config.build(|name| env.get(name).cloned(), config.mqtt_max_payload_size, &registry)
+                     .unwrap_or_else(|e| panic!("Wrong config of weather source {name}: {e}"))
+    };
+    // immutable, all time live, multithreading read access
+    let weather_sources = source_configs.map(build_source);
+    let backfill_source = build_source(backfill_config);
 
-        return Ok((transmitter, handler));
+    if std::env::args().any(|arg| arg == "--list-sources") {
+        for source in &weather_sources {
+            println!("{}", describe_source(source));
+        }
+        // provided once at startup if KP_BACKFILL is set
+        println!("backfill of {}", describe_source(&backfill_source));
+        return;
     }
 
-    async fn send_to_broker(&self, topic: &str, payload: String) -> Result<(), String> {
-        let full_topic = Self::make_full_topic(topic, &self.settings.config);
-        println!("\tMQTT publish topic {} with payload: ", full_topic);
-        println!("\t\t{:#}", payload);
-        let mut mut_client = self.client.lock().expect("Error when locking MQTT client mutex");
-        mut_client.publish(full_topic, QoS::AtLeastOnce, false, payload.as_bytes())
-            .map_err(|e| format!("MQTT publish error: {e}"))
+    if std::env::args().any(|arg| arg == "--self-test") {
+        let mut passed = true;
+        for source in weather_sources.iter().chain(std::iter::once(&backfill_source)) {
+            match self_test_source(source) {
+                Ok(count) => println!("PASS {} ({}): {count} payloads", source.mqtt_topic_name, source.convert.name()),
+                Err(e) => {
+                    println!("FAIL {} ({}): {e}", source.mqtt_topic_name, source.convert.name());
+                    passed = false;
+                },
+            }
+        }
+        std::process::exit(if passed { 0 } else { 1 });
     }
 
-    fn make_full_topic(sensor_name: &str, config: &Config) -> String {
-        let full_topic = config.mqtt_base_topic.clone() + "/" + &config.mqtt_device_name + "_" + sensor_name + "/state";
-        return full_topic;
+    if std::env::args().any(|arg| arg == "--validate-config") {
+        let mut passed = true;
+        let mut report = |check: String, result: Result<String, ProviderError>| match result {
+            Ok(details) => println!("PASS {check}: {details}"),
+            Err(e) => {
+                println!("FAIL {check}: {e}");
+                passed = false;
+            },
+        };
+        match config.brokers() {
+            Ok(brokers) => for (host, port) in brokers {
+                let result = check_mqtt_connection(&config, &host, port).await;
+                report(format!("MQTT broker {host}:{port}"), result.map(|_| "connected".to_string()));
+            },
+            Err(e) => report("MQTT brokers".to_string(), Err(e)),
+        }
+        let fetcher = TReqwestFetcher::new(&config).unwrap();
+        for source in &weather_sources {
+            let validators = TCacheValidators::default();
+            let result = fetcher.fetch(&source.source_url, &source.provide_options.headers, &validators).await
+                                .map(|fetched| match fetched {
+                                    TFetchResult::Modified { body, .. } => format!("{} bytes", body.len()),
+                                    TFetchResult::NotModified => "not modified".to_string(),
+                                });
+            report(format!("fetch {} ({})", source.mqtt_topic_name, source.source_url), result);
+        }
+        std::process::exit(if passed { 0 } else { 1 });
     }
-}
-
 
-#[derive(Envconfig, Debug)]
-struct Config {
-    #[envconfig(from = "MQTT_BROKER_HOST", default = "localhost")]
-    pub mqtt_host: String,
-
-    #[envconfig(from = "MQTT_BROKER_PORT", default = "1883")]
-    pub mqtt_port: u16,
-
-    #[envconfig(from = "MQTT_BROKER_KEEP_ALIVE", default = "5")]
-    pub mqtt_keep_alive: u16,
-
-    #[envconfig(from = "MQTT_BROKER_BASE_TOPIC", default = "homeassistant/sensor")]
-    pub mqtt_base_topic: String,
-
-    #[envconfig(from = "MQTT_DEVICE_NAME", default = "cubieboard")]
-    pub mqtt_device_name: String,
-
-    #[envconfig(from = "KP_RELEASE_INTERVAL_S", default = "600")]   // 10 min
-    pub kp_release_interval_s: u16,
-
-    #[envconfig(from = "KP_INST_INTERVAL_S", default = "300")]     // 5 min
-    pub kp_inst_interval_s: u16,
-}
-
-
-#[tokio::main]
-async fn main() {
-    // immutable, all time live, multithreading read access
-    let config = Config::init_from_env().unwrap();
+    if let Some(name) = arg_value(std::env::args(), "--source") {
+        let Some(source) = weather_sources.iter().find(|source| source.mqtt_topic_name == name) else {
+            println!("Unknown weather source {name}, see --list-sources");
+            std::process::exit(1);
+        };
+        let fetcher = TReqwestFetcher::new(&config).unwrap();
+        let config = Arc::new(config);
+        let transmitter = TStdoutTransmitter { config: config.clone() };
+        let wprovider = TWeatherProvider::new(config, Box::new(fetcher), vec![Box::new(transmitter)],
+                                              Arc::new(TMetrics::new()));
+        match wprovider.provide(source).await {
+            Ok(_) => println!("Provided successfully ws {name}"),
+            Err(e) => {
+                println!("Error during providing weather source {name}: {e}");
+                std::process::exit(1);
+            },
+        }
+        return;
+    }
 
-    println!("Using config:\n{:?}", config);
+    let metrics = Arc::new(TMetrics::new());
+    if config.metrics_port != 0 {
+        metrics::serve(metrics.clone(), config.metrics_port);
+    }
 
-    // immutable, all time live, multithreading read access
-    let weather_sources = [
-        TWeatherSource { source_url: "https://services.swpc.noaa.gov/products/noaa-planetary-k-index.json",
-                         mqtt_topic_name: "noaa_kp",
-                         request_interval_s: config.kp_release_interval_s,
-                         convert: converter_kp
-                        },
-        TWeatherSource { source_url: "https://services.swpc.noaa.gov/json/planetary_k_index_1m.json",
-                         mqtt_topic_name: "noaa_kp_inst",
-                         request_interval_s: config.kp_inst_interval_s,
-                         convert: converter_kp_inst
-                       },
-        TWeatherSource { source_url: "https://services.swpc.noaa.gov/json/goes/primary/integral-protons-plot-6-hour.json",
-                         mqtt_topic_name: "noaa_flux",
-                         request_interval_s: config.kp_inst_interval_s,
-                         convert: converter_flux
-                       },
-        TWeatherSource { source_url: "https://services.swpc.noaa.gov/text/3-day-forecast.txt",
-                         mqtt_topic_name: "noaa_sw_forecast",
-                         request_interval_s: config.kp_release_interval_s,
-                         convert: converter_sw_forecast
-                       },
-    ];
-
-    let (mqtt, conn_handler) = TMQTTransmitter::new(TMQTTSettings {
-                                        name: "weather-provider",
-                                        config: Arc::new(config)
-                                    }).unwrap();
+    let fetcher = TReqwestFetcher::new(&config).unwrap();
+    let config = Arc::new(config);
+
+    let brokers = config.brokers().unwrap_or_else(|e| panic!("Wrong config: {e}"));
+    let (commands, command_receiver) = tokio::sync::mpsc::unbounded_channel();
+    let mut transmitters: Vec<Box<dyn Transmitter>> = Vec::new();
+    let mut conn_handlers = Vec::new();
+    for (host, port) in brokers {
+        let (mqtt, conn_handler) = TMQTTransmitter::new(TMQTTSettings {
+                                            name: "weather-provider",
+                                            host,
+                                            port,
+                                            config: config.clone(),
+                                            metrics: metrics.clone(),
+                                            commands: config.mqtt_commands.then(|| commands.clone()),
+                                        }).unwrap();
+        transmitters.push(Box::new(mqtt));
+        conn_handlers.push(conn_handler);
+    }
+    if let Some(url) = &config.webhook_url {
+        transmitters.push(Box::new(TWebhookTransmitter::new(url.clone()).unwrap()));
+    }
+    if let Some(url) = &config.influxdb_write_url {
+        transmitters.push(Box::new(TInfluxTransmitter::new(&config, url.clone()).unwrap()));
+    }
 
     // TODO: waiting for connection
 
-    let wprovider = TWeatherProvider {
-        transmitter: mqtt
-    };
+    let wprovider = TWeatherProvider::new(config.clone(), Box::new(fetcher), transmitters, metrics);
+    if config.mqtt_discovery {
+        if let Err(e) = wprovider.announce(&weather_sources).await {
+            println!("Error during publishing discovery configs: {e}");
+        }
+    }
 
+    wprovider.track_failures(weather_sources.iter().map(|source| source.mqtt_topic_name));
     let wprovider_ref = Arc::new(wprovider);
+    if kp_backfill {
+        start_backfill_task(wprovider_ref.clone(), backfill_source);
+    }
+    let sources = weather_sources.to_vec();
+    let mut intervals = HashMap::new();
     for source in weather_sources {
-        start_task(wprovider_ref.clone(), source);
+        let (interval, interval_receiver) = tokio::sync::watch::channel(source.request_interval);
+        intervals.insert(source.mqtt_topic_name, interval);
+        start_task(wprovider_ref.clone(), source, interval_receiver);
+    }
+    if config.mqtt_commands {
+        start_command_task(wprovider_ref.clone(), sources, intervals, command_receiver);
+    }
+    if let (Some(topic), Some(period)) = (&config.combined_topic, config.combined_interval.enabled()) {
+        start_combined_task(wprovider_ref.clone(), topic.clone(), period);
+    }
+    if let Some(period) = config.heartbeat_interval.enabled() {
+        start_heartbeat_task(wprovider_ref.clone(), period);
     }
 
-    let _ = tokio::join!(conn_handler);
+    let conn_handlers = async {
+        for conn_handler in conn_handlers {
+            let _ = conn_handler.await;
+        }
+    };
+    tokio::select! {
+        _ = conn_handlers => {},
+        _ = wprovider_ref.wait_all_failing() => {
+            println!("All weather sources failed {} times in a row, exiting", config.exit_after_failures);
+            std::process::exit(1);
+        },
+    }
 }
 
-fn start_task(wprovider_ref: Arc<TWeatherProvider>, ws: TWeatherSource) {
-    println!("Starting task for weather source {} ...", ws.mqtt_topic_name);
-
-    task::spawn(async move {
-        println!("Done. Task for weather source {} started", ws.mqtt_topic_name);
-        let mut interval = interval(Duration::from_secs(ws.request_interval_s.into()));
-        loop {
-            println!("\tWaiting... {}\n", ws.mqtt_topic_name);
-            interval.tick().await;
-            // TODO: limit max time for loading and sending
-            println!("\tStart providing ws {} ... ", ws.mqtt_topic_name);
-            wprovider_ref.provide(&ws).await.expect(format!("\tError during providing weather source {}", ws.mqtt_topic_name).as_str());
-            println!("\tProvided successfully ws {}", ws.mqtt_topic_name);
-        }
-    });
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_arg_value() {
+        let args = |line: &str| line.split(' ').map(str::to_string).collect::<Vec<_>>().into_iter();
+        assert_eq!(arg_value(args("weather-provider --source noaa_flux"), "--source"), Some("noaa_flux".to_string()));
+        assert_eq!(arg_value(args("weather-provider --source"), "--source"), None);
+        assert_eq!(arg_value(args("weather-provider --list-sources"), "--source"), None);
+    }
 }
@@ -0,0 +1,248 @@
+use hyper::service::{make_service_fn, service_fn};
+use hyper::{Body, Method, Request, Response, Server, StatusCode};
+use prometheus::{Encoder, IntCounterVec, IntGaugeVec, Opts, Registry, TextEncoder};
+use std::collections::BTreeMap;
+use std::convert::Infallible;
+use std::sync::{Arc, Mutex};
+use tokio::task;
+
+
+#[derive(Debug, Clone, Default)]
+struct TSourceMetrics {
+    last_success_timestamp: i64,
+    // failed fetches or conversions since the last successful one
+    consecutive_errors: u64,
+    // latest state payload, JSON or scalar
+    latest: Option<serde_json::Value>,
+}
+
+// Prometheus metrics per source label and latest state of sources for `/latest`
+pub struct TMetrics {
+    registry: Registry,
+    fetch_success_total: IntCounterVec,
+    fetch_error_total: IntCounterVec,
+    publish_total: IntCounterVec,
+    last_success_timestamp: IntGaugeVec,
+    sources: Mutex<BTreeMap<String, TSourceMetrics>>,
+}
+
+impl Default for TMetrics {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl TMetrics {
+    pub fn new() -> Self {
+        let registry = Registry::new();
+        let counter = |name: &str, help: &str| {
+            let counter = IntCounterVec::new(Opts::new(name, help), &["source"]).expect("Wrong metric definition");
+            registry.register(Box::new(counter.clone())).expect("Error when registering metric");
+            counter
+        };
+        let fetch_success_total = counter("fetch_success_total", "Number of successful fetches and conversions");
+        let fetch_error_total = counter("fetch_error_total", "Number of failed fetches or conversions");
+        let publish_total = counter("publish_total", "Number of messages published to MQTT broker");
+        let last_success_timestamp = IntGaugeVec::new(
+            Opts::new("last_success_timestamp", "Unix time of the last successful fetch"), &["source"])
+            .expect("Wrong metric definition");
+        registry.register(Box::new(last_success_timestamp.clone())).expect("Error when registering metric");
+        let build_info = IntGaugeVec::new(Opts::new("build_info", "Version and git commit of running build"),
+                                          &["version", "commit"])
+            .expect("Wrong metric definition");
+        build_info.with_label_values(&[env!("CARGO_PKG_VERSION"), env!("GIT_COMMIT")]).set(1);
+        registry.register(Box::new(build_info)).expect("Error when registering metric");
+        Self {
+            registry,
+            fetch_success_total,
+            fetch_error_total,
+            publish_total,
+            last_success_timestamp,
+            sources: Mutex::new(BTreeMap::new()),
+        }
+    }
+
+    pub fn fetch_success(&self, source: &str) {
+        let now = chrono::Utc::now().timestamp();
+        self.fetch_success_total.with_label_values(&[source]).inc();
+        self.last_success_timestamp.with_label_values(&[source]).set(now);
+        self.update(source, |m| {
+            m.last_success_timestamp = now;
+            m.consecutive_errors = 0;
+        });
+    }
+
+    pub fn fetch_error(&self, source: &str) {
+        self.fetch_error_total.with_label_values(&[source]).inc();
+        self.update(source, |m| m.consecutive_errors += 1);
+    }
+
+    // Remembers converted state payload for pulling clients, non-JSON payload is kept as string
+    pub fn latest(&self, source: &str, payload: &str) {
+        let value = serde_json::from_str(payload).unwrap_or_else(|_| serde_json::Value::from(payload));
+        self.update(source, |m| m.latest = Some(value));
+    }
+
+    pub fn publish(&self, source: &str) {
+        self.publish_total.with_label_values(&[source]).inc();
+    }
+
+    fn update(&self, source: &str, f: impl FnOnce(&mut TSourceMetrics)) {
+        let mut sources = self.sources.lock().expect("Error when locking metrics mutex");
+        f(sources.entry(source.to_string()).or_default());
+    }
+
+    // Prometheus text exposition format
+    pub fn render(&self) -> String {
+        let mut buffer = Vec::new();
+        if let Err(e) = TextEncoder::new().encode(&self.registry.gather(), &mut buffer) {
+            println!("Metrics encoding error: {e}");
+        }
+        String::from_utf8_lossy(&buffer).into_owned()
+    }
+
+    // Build and latest values of all sources as array of rows, e.g. for Grafana JSON/Infinity datasource
+    pub fn render_json(&self) -> String {
+        let sources = self.sources.lock().expect("Error when locking metrics mutex");
+        let rows: Vec<serde_json::Value> = sources.iter().map(|(source, metrics)| {
+            let last_success = chrono::DateTime::from_timestamp(metrics.last_success_timestamp, 0)
+                .filter(|_| metrics.last_success_timestamp != 0)
+                .map(|time| time.to_rfc3339_opts(chrono::SecondsFormat::Secs, true));
+            serde_json::json!({
+                "source": source,
+                "value": metrics.latest,
+                "last_success": last_success,
+                "healthy": metrics.consecutive_errors == 0,
+                "consecutive_errors": metrics.consecutive_errors,
+            })
+        }).collect();
+        serde_json::json!({
+            "version": env!("CARGO_PKG_VERSION"),
+            "commit": env!("GIT_COMMIT"),
+            "sources": rows,
+        }).to_string()
+    }
+}
+
+// Answers `GET /metrics` and `GET /latest`, other requests get 404
+fn route(metrics: &TMetrics, request: &Request<Body>) -> Response<Body> {
+    let (content_type, body) = match (request.method(), request.uri().path()) {
+        (&Method::GET, "/metrics") => (TextEncoder::new().format_type().to_string(), metrics.render()),
+        (&Method::GET, "/latest") => ("application/json".to_string(), metrics.render_json()),
+        _ => {
+            let mut response = Response::new(Body::empty());
+            *response.status_mut() = StatusCode::NOT_FOUND;
+            return response;
+        },
+    };
+    let mut response = Response::new(Body::from(body));
+    if let Ok(content_type) = content_type.parse() {
+        response.headers_mut().insert(hyper::header::CONTENT_TYPE, content_type);
+    }
+    response
+}
+
+// HTTP server of metrics and latest values
+pub fn serve(metrics: Arc<TMetrics>, port: u16) -> task::JoinHandle<()> {
+    task::spawn(async move {
+        let make_service = make_service_fn(move |_| {
+            let metrics = metrics.clone();
+            async move {
+                Ok::<_, Infallible>(service_fn(move |request| {
+                    let response = route(&metrics, &request);
+                    async move { Ok::<_, Infallible>(response) }
+                }))
+            }
+        });
+        let server = match Server::try_bind(&([0, 0, 0, 0], port).into()) {
+            Ok(builder) => builder.serve(make_service),
+            Err(e) => {
+                println!("Metrics server bind error: {e}");
+                return;
+            },
+        };
+        println!("Metrics server listening on port {port}");
+        if let Err(e) = server.await {
+            println!("Metrics server error: {e}");
+        }
+    })
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_render_metrics() {
+        let metrics = TMetrics::new();
+        metrics.fetch_success("noaa_kp");
+        metrics.fetch_error("noaa_kp");
+        metrics.fetch_error("noaa_kp");
+        metrics.publish("noaa_kp");
+        let text = metrics.render();
+        assert!(text.contains(&format!("build_info{{commit=\"{}\",version=\"{}\"}} 1\n", env!("GIT_COMMIT"),
+                                       env!("CARGO_PKG_VERSION"))));
+        assert!(text.contains("# TYPE fetch_success_total counter\n"));
+        assert!(text.contains("fetch_success_total{source=\"noaa_kp\"} 1\n"));
+        assert!(text.contains("fetch_error_total{source=\"noaa_kp\"} 2\n"));
+        assert!(text.contains("publish_total{source=\"noaa_kp\"} 1\n"));
+        assert!(text.contains("# TYPE last_success_timestamp gauge\n"));
+    }
+
+    #[tokio::test]
+    async fn test_serve_split_request() {
+        use tokio::io::{AsyncReadExt, AsyncWriteExt};
+        let port = std::net::TcpListener::bind("127.0.0.1:0").unwrap().local_addr().unwrap().port();
+        let metrics = Arc::new(TMetrics::new());
+        metrics.fetch_success("noaa_kp");
+        serve(metrics, port);
+        let mut stream = loop {
+            match tokio::net::TcpStream::connect(("127.0.0.1", port)).await {
+                Ok(stream) => break stream,
+                Err(_) => tokio::time::sleep(std::time::Duration::from_millis(10)).await,
+            }
+        };
+        // request line arrives in two TCP segments
+        stream.write_all(b"GET /met").await.unwrap();
+        stream.flush().await.unwrap();
+        tokio::time::sleep(std::time::Duration::from_millis(50)).await;
+        stream.write_all(b"rics HTTP/1.1\r\nHost: localhost\r\nConnection: close\r\n\r\n").await.unwrap();
+        let mut response = String::new();
+        stream.read_to_string(&mut response).await.unwrap();
+        assert!(response.starts_with("HTTP/1.1 200 OK"));
+        assert!(response.contains("fetch_success_total{source=\"noaa_kp\"} 1\n"));
+    }
+
+    #[test]
+    fn test_route_not_found() {
+        let request = Request::builder().uri("/other").body(Body::empty()).unwrap();
+        assert_eq!(route(&TMetrics::new(), &request).status(), StatusCode::NOT_FOUND);
+    }
+
+    #[test]
+    fn test_render_json() {
+        let metrics = TMetrics::new();
+        metrics.fetch_success("noaa_kp_inst");
+        metrics.latest("noaa_kp_inst", r#"{"time_tag":"00:29 01-05-2024","kp":4.0}"#);
+        metrics.fetch_error("noaa_flux");
+        metrics.update("noaa_flux", |m| m.last_success_timestamp = 1714523340);
+        metrics.latest("noaa_flux", "not json");
+        let latest: serde_json::Value = serde_json::from_str(&metrics.render_json()).unwrap();
+        assert_eq!((&latest["version"], &latest["commit"]), (&env!("CARGO_PKG_VERSION").into(),
+                                                              &env!("GIT_COMMIT").into()));
+        let rows = &latest["sources"];
+        assert_eq!(rows[0].to_string(), "{\"consecutive_errors\":1,\"healthy\":false,\
+                                         \"last_success\":\"2024-05-01T00:29:00Z\",\"source\":\"noaa_flux\",\
+                                         \"value\":\"not json\"}");
+        assert_eq!(rows[1]["value"].to_string(), r#"{"kp":4.0,"time_tag":"00:29 01-05-2024"}"#);
+        assert_eq!(rows[1]["healthy"], true);
+        assert!(rows[1]["last_success"].as_str().unwrap().ends_with('Z'));
+        metrics.fetch_success("noaa_flux");
+        let latest: serde_json::Value = serde_json::from_str(&metrics.render_json()).unwrap();
+        assert_eq!(latest["sources"][0]["healthy"], true);
+        let latest: serde_json::Value = serde_json::from_str(&TMetrics::new().render_json()).unwrap();
+        assert_eq!(latest["sources"], serde_json::json!([]));
+    }
+}
@@ -1,15 +1,17 @@
 extern crate nom;
 use nom::{
     bytes::complete::{tag, take_until},
-    character::complete::{alpha1, alphanumeric1, digit1, line_ending, multispace1, not_line_ending, space0, space1},
-    combinator::opt,
+    character::complete::{alpha1, alphanumeric1, digit1, line_ending, multispace0, multispace1, not_line_ending, space0,
+                          space1},
+    combinator::{map_res, opt, verify},
     multi::many1,
     number::complete::float,
     sequence::{delimited, preceded, tuple},
     Finish, IResult,
     error::{Error, ErrorKind, ParseError}
 };
-use serde::Serialize;
+use chrono::{Datelike, NaiveDate};
+use serde::{Serialize, Serializer};
 use std::str::FromStr;
 
 #[derive(Serialize, Debug, Clone, Default)]
@@ -29,13 +31,50 @@ pub struct SRSRBForecast {
     pub s5: u8,
 }
 
+// Radio blackout forecast as published, grades are R1..R5
+#[derive(Serialize)]
+struct RBForecastJson<'a> {
+    date: &'a str,
+    r1: u8,
+    r2: u8,
+    r3: u8,
+    r4: u8,
+    r5: u8,
+}
+
+fn serialize_rb<S: Serializer>(rb: &[SRSRBForecast], serializer: S) -> Result<S::Ok, S::Error> {
+    serializer.collect_seq(rb.iter().map(|rb| RBForecastJson {
+        date: &rb.date,
+        r1: rb.s1,
+        r2: rb.s2,
+        r3: rb.s3,
+        r4: rb.s4,
+        r5: rb.s5,
+    }))
+}
+
+#[derive(Serialize, Debug, Clone, Default, PartialEq)]
+pub struct KPSummary {
+    pub observed: f32,
+    pub expected: f32,
+    pub expected_scale: Option<String>,
+}
+
 #[derive(Serialize, Debug, Clone, Default)]
 pub struct SWForecast {
+    pub kp_summary: Option<KPSummary>,
     pub kp: Vec<KPForecast>,
     pub srs: Vec<SRSRBForecast>,
+    // fields s1..s5 hold R1..R5 grades
+    #[serde(serialize_with = "serialize_rb")]
     pub rb: Vec<SRSRBForecast>,
 }
 
+// Table headers, each table is in its own section
+const KP_HEADER: &str = "NOAA Kp index breakdown";
+const SRS_HEADER: &str = "Solar Radiation Storm Forecast";
+const RB_HEADER: &str = "Radio Blackout Forecast";
+
 // Common methods
 
 fn parse_date(input: &str) -> IResult<&str, String> {
@@ -45,22 +84,107 @@ fn parse_date(input: &str) -> IResult<&str, String> {
     Ok((input, format!("{} {}", month, day)))
 }
 
+// NOAA dates like "May 01 2024" as real dates for sorting, string order breaks across months
+pub fn date_key(date: &str) -> Option<NaiveDate> {
+    NaiveDate::parse_from_str(date, "%b %d %Y").ok()
+}
+
+// line ending (LF or CRLF) with optional trailing whitespace before it
+fn line_end(input: &str) -> IResult<&str, &str> {
+    preceded(space0, line_ending)(input)
+}
+
+// like take_until, but phrase must start line (after optional indent), so mentions in prose don't match
+fn take_until_line_start<'a>(input: &'a str, phrase: &str) -> IResult<&'a str, &'a str> {
+    let mut offset = 0;
+    loop {
+        let (rest, _) = take_until(phrase)(&input[offset..])?;
+        let position = input.len() - rest.len();
+        let before = input[..position].trim_end_matches([' ', '\t']);
+        if before.is_empty() || before.ends_with('\n') {
+            return Ok((rest, &input[..position]));
+        }
+        offset = position + phrase.len();
+    }
+}
+
+// line starting with lettered section marker, e.g. "B. NOAA Solar Radiation Activity Observation and Forecast"
+fn is_section_marker(line: &str) -> bool {
+    matches!(line.as_bytes(), [letter, b'.', space, ..] if letter.is_ascii_uppercase() && space.is_ascii_whitespace())
+}
+
+// parser that finds lettered section by its marker, e.g. "A.", and returns its text up to next section
+fn parse_section<'a>(input: &'a str, marker: &str) -> IResult<&'a str, &'a str> {
+    let (input, _) = take_until_line_start(input, marker)?;
+    let (input, _) = tuple((tag(marker), space1))(input)?;
+    let end = input.match_indices('\n').map(|(index, _)| index + 1)
+                   .find(|&start| is_section_marker(&input[start..]))
+                   .unwrap_or(input.len());
+    Ok((&input[end..], &input[..end]))
+}
+
 // parser that finds header and dates
 fn parse_header<'a>(input: &'a str, header: &str) -> IResult<&'a str, Vec<String>> {
-    let (input, _) = take_until(header)(input)?;
+    let (input, _) = take_until_line_start(input, header)?;
     let (input, _) = tuple((tag(header), multispace1))(input)?;
     let (input, dates_wyear) = not_line_ending(input)?;
-    let year = " ".to_string() + dates_wyear.split(' ').last().unwrap();
-    let (input, _) = line_ending(input)?;
-    let (input, _) = line_ending(input)?;
+    let year = " ".to_string() + dates_wyear.split_whitespace().next_back().unwrap_or_default();
+    let (input, _) = line_end(input)?;
+    let (input, _) = line_end(input)?;
     let (input, mut dates) = many1(preceded(space1, parse_date))(input)?;
     for date in &mut dates {
         *date += year.as_str();
     }
-    let (input, _) = line_ending(input)?;
+    // table may span new year, e.g. "Dec 31-Jan 02 2025", then dates before January are of previous year
+    for index in (0..dates.len().saturating_sub(1)).rev() {
+        if let (Some(date), Some(next)) = (date_key(&dates[index]), date_key(&dates[index + 1])) {
+            if let Some(date) = date.with_year(date.year() - 1).filter(|_| date > next) {
+                dates[index] = date.format("%b %d %Y").to_string();
+            }
+        }
+    }
+    let (input, _) = line_end(input)?;
     Ok((input, dates))
 }
 
+// Kp summary sentences
+
+// parser for optional parenthetical after value, e.g. "(NOAA Scale G1)" returns scale "G1".
+// NOAA wraps sentences at fixed width, so line breaks may appear anywhere inside, e.g. "(NOAA Scale\nG1)".
+fn parse_scale_note(input: &str) -> IResult<&str, Option<String>> {
+    let (input, _) = multispace0(input)?;
+    let (input, note) = opt(delimited(tag("("), take_until(")"), tag(")")))(input)?;
+    let scale = note.and_then(|note| match note.split_whitespace().collect::<Vec<_>>()[..] {
+        ["NOAA", "Scale", scale] => Some(scale.to_string()),
+        _ => None,
+    });
+    Ok((input, scale))
+}
+
+// parser for "The greatest observed 3 hr Kp over the past 24 hours was 4 (below NOAA Scale levels)."
+fn parse_kp_observed(input: &str) -> IResult<&str, f32> {
+    let (input, _) = take_until("The greatest observed 3 hr Kp")(input)?;
+    let (input, _) = take_until(" was ")(input)?;
+    let (input, _) = tag(" was ")(input)?;
+    float(input)
+}
+
+// parser for "The greatest expected 3 hr Kp for May 01-May 03 2024 is 4.67 (NOAA Scale G1)."
+fn parse_kp_expected(input: &str) -> IResult<&str, (f32, Option<String>)> {
+    let (input, _) = take_until("The greatest expected 3 hr Kp")(input)?;
+    let (input, _) = take_until(" is ")(input)?;
+    let (input, _) = tag(" is ")(input)?;
+    let (input, value) = float(input)?;
+    let (input, scale) = parse_scale_note(input)?;
+    Ok((input, (value, scale)))
+}
+
+fn parse_kp_summary(input: &str) -> IResult<&str, KPSummary> {
+    let (input, observed) = parse_kp_observed(input)?;
+    let (input, (expected, expected_scale)) = parse_kp_expected(input)?;
+    Ok((input, KPSummary { observed, expected, expected_scale }))
+}
+
 // Kp forecast
 
 fn parse_kp_val(input: &str) -> IResult<&str, f32> {
@@ -70,12 +194,17 @@ fn parse_kp_val(input: &str) -> IResult<&str, f32> {
     Ok((input, kp_value))
 }
 
+// parser for unsigned number within `range`, out-of-range or overflowing number is parse error
+fn parse_u8_in<'a>(range: std::ops::RangeInclusive<u8>) -> impl FnMut(&'a str) -> IResult<&'a str, u8> {
+    verify(map_res(digit1, u8::from_str), move |value| range.contains(value))
+}
+
 fn parse_hours_interval(input: &str) -> IResult<&str, (u8, u8)> {
-    let (input, start) = digit1(input)?;
+    let (input, start) = parse_u8_in(0..=24)(input)?;
     let (input, _) = tag("-")(input)?;
-    let (input, end) = digit1(input)?;
+    let (input, end) = parse_u8_in(0..=24)(input)?;
     let (input, _) = tag("UT")(input)?;
-    Ok((input, (u8::from_str(start).unwrap(), u8::from_str(end).unwrap())))
+    Ok((input, (start, end)))
 }
 
 // parser for rows with interval and Kp value
@@ -88,27 +217,28 @@ fn parse_kp_row(input: &str) -> IResult<&str, (u8, u8, Vec<f32>)> {
 }
 
 fn parse_kp_forecast(input: &str) -> IResult<&str, Vec<KPForecast>> {
-    let (input, dates) = parse_header(input, "NOAA Kp index breakdown")?;
+    let (input, dates) = parse_header(input, KP_HEADER)?;
     let (input, rows) = many1(parse_kp_row)(input)?;
 
+    if rows.iter().any(|(_, _, kps)| kps.len() != dates.len()) {
+        let errmsg: &str = "Number of dates are not correspond to number of found values";
+        return Err(nom::Err::Error(Error::from_error_kind(errmsg, ErrorKind::Fail)));
+    }
+
     let mut results = Vec::new();
     for (_, time_range_end, kps) in rows {
-        if kps.len() != dates.len() {
-            let errmsg: &str = "Number of dates are not correspond to number of found values";
-            return Err(nom::Err::Error(Error::from_error_kind(errmsg, ErrorKind::Fail)));
-        }
-        for (index, kp) in kps.into_iter().enumerate() {
-            let date = &dates[index];
+        for (date, kp) in dates.iter().zip(kps) {
             results.push(KPForecast {
                 date: date.clone(),
-                hour: time_range_end,
+                // hour is end of interval, so "21-00UT" is hour 24 of its date
+                hour: if time_range_end == 0 { 24 } else { time_range_end },
                 value: kp,
             });
         }
     }
 
-    // sort
-    results.sort_by(|kpf1, kpf2| kpf1.date.cmp(&kpf2.date));
+    // chronological order
+    results.sort_by_key(|kpf| (date_key(&kpf.date), kpf.hour));
 
     Ok((input, results))
 }
@@ -116,27 +246,23 @@ fn parse_kp_forecast(input: &str) -> IResult<&str, Vec<KPForecast>> {
 // Solar and Radio Blackout stroms forecast
 
 fn parse_prcnt_val(input: &str) -> IResult<&str, u8> {
-    let (input, value) = digit1(input)?;
+    let (input, value) = parse_u8_in(0..=100)(input)?;
     let (input, _) = tag("%")(input)?;
-    Ok((input, u8::from_str(value).unwrap()))
+    Ok((input, value))
 }
 
 // Parser that returns max and min storm grades.
-// If it finds "or greater" phrase then max storm grade equals min grade + 1
+// "or greater" phrase means all grades from min grade up to 5
 fn parse_solar_rb_storms(input: &str, storm_type: char) -> IResult<&str, (u8, u8)> {
-    let (input, (_, s_min_str)) = tuple((tag(storm_type.to_string().as_str()), digit1))(input)?;
-    let s_min = u8::from_str(s_min_str).unwrap();
-    let mut s_max = s_min + 1;
-    if s_max > 5 {
-        s_max = 5;
-    };
+    let (input, (_, s_min)) = tuple((tag(storm_type.to_string().as_str()), parse_u8_in(1..=5)))(input)?;
+    let mut s_max = 5;
     let (input, greater) = opt(tag(" or greater"))(input)?;
     let input = match greater {
         Some(_) => input,
         None => {
             let (input, _) = tag("-")(input)?;
-            let (input, (_, max)) = tuple((tag(storm_type.to_string().as_str()), digit1))(input)?;
-            s_max = u8::from_str(max).unwrap();
+            let (input, (_, max)) = tuple((tag(storm_type.to_string().as_str()), parse_u8_in(1..=5)))(input)?;
+            s_max = max;
             input
         },
     };
@@ -180,36 +306,71 @@ fn parse_srs_rb_forecast<'a>(input: &'a str, header_phrase: &str, storm_type: ch
                     results.last_mut().unwrap()
                 },
             };
+            // grades are in 1..=5, checked by parse_solar_rb_storms
             let srs_vec = [&mut srs.s1, &mut srs.s2, &mut srs.s3, &mut srs.s4, &mut srs.s5];
-            assert!(s_min >= 1 && s_min <= 5);
-            assert!(s_max >= 1 && s_max <= 5);
             for si in (s_min - 1)..s_max {
                 *srs_vec[usize::from(si)] = value;
             }
         }
     }
-    // sort
-    results.sort_by(|elm1, elm2| elm1.date.cmp(&elm2.date));
+    // chronological order
+    results.sort_by_key(|srs| date_key(&srs.date));
 
     Ok((input, results))
 }
 
 fn parse_srs_forecast(input: &str) -> IResult<&str, Vec<SRSRBForecast>> {
-    parse_srs_rb_forecast(input, "Solar Radiation Storm Forecast", 'S')
+    parse_srs_rb_forecast(input, SRS_HEADER, 'S')
 }
 
 fn parse_rb_forecast(input: &str) -> IResult<&str, Vec<SRSRBForecast>> {
-    parse_srs_rb_forecast(input, "Radio Blackout Forecast", 'R')
+    parse_srs_rb_forecast(input, RB_HEADER, 'R')
+}
+
+// Section may be omitted by NOAA, e.g. during data issues, then next sections are searched in the same text
+fn optional_section<'a>(input: &'a str, marker: &str) -> (&'a str, Option<&'a str>) {
+    match parse_section(input, marker).finish() {
+        Ok((rest, section)) => (rest, Some(section)),
+        Err(_) => (input, None),
+    }
+}
+
+// Missing section or table is empty, but found table must be parsed completely
+fn parse_optional_table<'a, T>(section: Option<&'a str>, header: &str,
+                               parser: fn(&'a str) -> IResult<&'a str, Vec<T>>) -> Result<Vec<T>, Error<&'a str>> {
+    match section {
+        Some(section) if take_until_line_start(section, header).is_ok() => {
+            parser(section).finish().map(|(_, table)| table)
+        },
+        _ => Ok(Vec::new()),
+    }
 }
 
 // Public interface
 
 // Parser for 3 day space weather forecast from NOAA text data.
+// Every table is searched only in its own section: A. geomagnetic activity, B. solar radiation, C. radio blackouts.
+// Missing tables are empty, forecast without any of them is error.
 pub fn parse_sw_forecast(input: &str) -> Result<SWForecast, String> {
-    let (input, kp_data) = parse_kp_forecast(input).finish().expect("Failed to parse text");
-    let (input, srs_data) = parse_srs_forecast(input).finish().expect("Failed to parse text");
-    let (_, rb_data) = parse_rb_forecast(input).finish().expect("Failed to parse text");
+    let kp_error = |e: Error<&str>| format!("Kp forecast parsing error: {:?}", e.code);
+    let (input, section_a) = optional_section(input, "A.");
+    // summary sentences are optional, they don't prevent parsing of the tables
+    let kp_summary = section_a.and_then(|section| parse_kp_summary(section).finish().ok()).map(|(_, summary)| summary);
+    let kp_data = parse_optional_table(section_a, KP_HEADER, parse_kp_forecast).map_err(kp_error)?;
+
+    let srs_error = |e: Error<&str>| format!("Solar radiation storm forecast parsing error: {:?}", e.code);
+    let (input, section_b) = optional_section(input, "B.");
+    let srs_data = parse_optional_table(section_b, SRS_HEADER, parse_srs_forecast).map_err(srs_error)?;
+
+    let rb_error = |e: Error<&str>| format!("Radio blackout forecast parsing error: {:?}", e.code);
+    let (_, section_c) = optional_section(input, "C.");
+    let rb_data = parse_optional_table(section_c, RB_HEADER, parse_rb_forecast).map_err(rb_error)?;
+
+    if kp_data.is_empty() && srs_data.is_empty() && rb_data.is_empty() {
+        return Err("no forecast tables found".to_string());
+    }
     Ok(SWForecast {
+        kp_summary,
         kp: kp_data,
         srs: srs_data,
         rb: rb_data,
@@ -278,6 +439,97 @@ Rationale: R1-2 (Minor-Moderate) radio blackouts due to M-class flare
 activity primarily from AR 3654 are likely on 01 May.
 ";
 
+    #[test]
+    fn test_parse_kp_summary() {
+        let (_, summary) = parse_kp_summary(SW_FORECAST_DATA1).finish().unwrap();
+        assert_eq!(summary, KPSummary { observed: 4.0, expected: 4.67, expected_scale: Some("G1".to_string()) });
+    }
+
+    #[test]
+    fn test_parse_scale_note_wrapped() {
+        // wrapping as in forecast issued 2024 May 01 0030 UTC
+        let text = "The greatest expected 3 hr Kp for May 01-May 03 2024 is 4.67 (NOAA Scale\nG1).";
+        assert_eq!(parse_kp_expected(text).finish().map(|(_, expected)| expected), Ok((4.67, Some("G1".to_string()))));
+        assert_eq!(parse_scale_note(" (NOAA\r\nScale G2)").finish(), Ok(("", Some("G2".to_string()))));
+        assert_eq!(parse_scale_note("\n(NOAA Scale G3)").finish(), Ok(("", Some("G3".to_string()))));
+        assert_eq!(parse_scale_note(" (below NOAA\nScale levels)").finish(), Ok(("", None)));
+    }
+
+    #[test]
+    fn test_parse_kp_summary_below_scale() {
+        let text: &str = "
+The greatest observed 3 hr Kp over the past 24 hours was 2 (below NOAA
+Scale levels).
+The greatest expected 3 hr Kp for May 01-May 03 2024 is 3.33 (below NOAA
+Scale levels).";
+        let (_, summary) = parse_kp_summary(text).finish().unwrap();
+        assert_eq!(summary, KPSummary { observed: 2.0, expected: 3.33, expected_scale: None });
+    }
+
+    #[test]
+    fn test_parse_sw_forecast_missing_table() {
+        assert_eq!(parse_sw_forecast("no tables here").unwrap_err(), "no forecast tables found");
+    }
+
+    #[test]
+    fn test_parse_sw_forecast_missing_section() {
+        let text = &SW_FORECAST_DATA1[..SW_FORECAST_DATA1.find("C. NOAA Radio Blackout").unwrap()];
+        let forecast = parse_sw_forecast(text).unwrap();
+        assert_eq!(forecast.kp.len(), 24);
+        assert_eq!(forecast.srs.len(), 3);
+        assert!(forecast.rb.is_empty());
+        // section without its table, following sections are still found
+        let text = SW_FORECAST_DATA1.replace("Solar Radiation Storm Forecast for", "Forecast for");
+        let forecast = parse_sw_forecast(&text).unwrap();
+        assert!(forecast.srs.is_empty());
+        assert_eq!(forecast.rb.len(), 3);
+        let text = SW_FORECAST_DATA1.replace("A. NOAA Geomagnetic", "NOAA Geomagnetic");
+        let forecast = parse_sw_forecast(&text).unwrap();
+        assert!(forecast.kp.is_empty() && forecast.kp_summary.is_none());
+        assert_eq!(forecast.srs.len(), 3);
+        // found table is still parsed completely
+        let text = SW_FORECAST_DATA1.replace("S1 or greater    5%", "S1 or greater    5o%");
+        assert_eq!(parse_sw_forecast(&text).unwrap_err(), "Solar radiation storm forecast parsing error: Tag");
+    }
+
+    #[test]
+    fn test_parse_sw_forecast_header_in_prose() {
+        // header phrases mentioned before their tables, in previous section and inside sentence
+        let text = SW_FORECAST_DATA1
+            .replace("hours of 01 May due to transient influences.",
+                     "hours of 01 May, see also\nSolar Radiation Storm Forecast for May 01.")
+            .replace("below S-scale storm level thresholds.",
+                     "below S-scale thresholds, as in last Solar Radiation Storm Forecast for May 01.");
+        let forecast = parse_sw_forecast(&text).unwrap();
+        assert_eq!(forecast.kp.len(), 24);
+        assert_eq!(forecast.srs.len(), 3);
+        assert_eq!(forecast.srs[0].s1, 5);
+        assert_eq!(forecast.rb[0].s1, 55);
+    }
+
+    #[test]
+    fn test_parse_section() {
+        let (rest, section) = parse_section(SW_FORECAST_DATA1, "B.").finish().unwrap();
+        assert!(section.starts_with("NOAA Solar Radiation Activity Observation and Forecast"));
+        assert!(section.contains("S1 or greater"));
+        assert!(rest.starts_with("C. NOAA Radio Blackout Activity and Forecast"));
+        // marker inside line isn't section
+        assert!(parse_section("see item A. below", "A.").finish().is_err());
+    }
+
+    #[test]
+    fn test_parse_sw_forecast_crlf_trailing_spaces() {
+        let text = SW_FORECAST_DATA1.replace('\n', "  \r\n");
+        let forecast = parse_sw_forecast(&text).unwrap();
+        assert_eq!(forecast.kp.len(), 24);
+        assert_eq!(forecast.kp[0].date, "May 01 2024");
+        assert_eq!(forecast.kp[23].value, 8.67);
+        assert_eq!(forecast.srs.len(), 3);
+        assert_eq!(forecast.rb.len(), 3);
+        assert_eq!(forecast.rb[0].s3, 10);
+        assert_eq!(forecast.kp_summary.map(|summary| summary.expected), Some(4.67));
+    }
+
     #[test]
     fn test_parse_kp_forecast() {
         #[rustfmt::skip]
@@ -289,7 +541,7 @@ activity primarily from AR 3654 are likely on 01 May.
             KPForecast { date: "May 01 2024".to_string(), hour: 15, value: 2.67 },
             KPForecast { date: "May 01 2024".to_string(), hour: 18, value: 2.33 },
             KPForecast { date: "May 01 2024".to_string(), hour: 21, value: 3.00 },
-            KPForecast { date: "May 01 2024".to_string(), hour: 0, value: 3.33 },
+            KPForecast { date: "May 01 2024".to_string(), hour: 24, value: 3.33 },
 
             KPForecast { date: "May 02 2024".to_string(), hour: 3, value: 3.67 },
             KPForecast { date: "May 02 2024".to_string(), hour: 6, value: 4.0 },
@@ -298,7 +550,7 @@ activity primarily from AR 3654 are likely on 01 May.
             KPForecast { date: "May 02 2024".to_string(), hour: 15, value: 6.0 },
             KPForecast { date: "May 02 2024".to_string(), hour: 18, value: 2.67 },
             KPForecast { date: "May 02 2024".to_string(), hour: 21, value: 3.67 },
-            KPForecast { date: "May 02 2024".to_string(), hour: 0, value: 3.67 },
+            KPForecast { date: "May 02 2024".to_string(), hour: 24, value: 3.67 },
 
             KPForecast { date: "May 03 2024".to_string(), hour: 3, value: 3.67 },
             KPForecast { date: "May 03 2024".to_string(), hour: 6, value: 3.33 },
@@ -307,7 +559,7 @@ activity primarily from AR 3654 are likely on 01 May.
             KPForecast { date: "May 03 2024".to_string(), hour: 15, value: 3.0 },
             KPForecast { date: "May 03 2024".to_string(), hour: 18, value: 3.33 },
             KPForecast { date: "May 03 2024".to_string(), hour: 21, value: 3.33 },
-            KPForecast { date: "May 03 2024".to_string(), hour: 0, value: 8.67 },
+            KPForecast { date: "May 03 2024".to_string(), hour: 24, value: 8.67 },
         ];
         let (_, kp_data) = parse_kp_forecast(SW_FORECAST_DATA1).finish().unwrap();
         for i in 0..kp_forecast1.len() {
@@ -321,9 +573,9 @@ activity primarily from AR 3654 are likely on 01 May.
     fn test_parse_srs_forecast() {
         #[rustfmt::skip]
         let srs_forecast1: Vec<SRSRBForecast> = vec![
-            SRSRBForecast { date: "May 01 2024".to_string(), s1: 5, s2: 5, s3: 0, s4: 0, s5: 0, },
-            SRSRBForecast { date: "May 02 2024".to_string(), s1: 5, s2: 5, s3: 0, s4: 0, s5: 0, },
-            SRSRBForecast { date: "May 03 2024".to_string(), s1: 5, s2: 5, s3: 0, s4: 0, s5: 0, },
+            SRSRBForecast { date: "May 01 2024".to_string(), s1: 5, s2: 5, s3: 5, s4: 5, s5: 5, },
+            SRSRBForecast { date: "May 02 2024".to_string(), s1: 5, s2: 5, s3: 5, s4: 5, s5: 5, },
+            SRSRBForecast { date: "May 03 2024".to_string(), s1: 5, s2: 5, s3: 5, s4: 5, s5: 5, },
         ];
         let (_, data) = parse_srs_forecast(SW_FORECAST_DATA1).finish().unwrap();
         for i in 0..srs_forecast1.len() {
@@ -340,9 +592,9 @@ activity primarily from AR 3654 are likely on 01 May.
     fn test_parse_rb_forecast() {
         #[rustfmt::skip]
         let rb_forecast1: Vec<SRSRBForecast> = vec![
-            SRSRBForecast { date: "May 01 2024".to_string(), s1: 55, s2: 55, s3: 10, s4: 10, s5: 0, },
-            SRSRBForecast { date: "May 02 2024".to_string(), s1: 45, s2: 45, s3: 10, s4: 10, s5: 0, },
-            SRSRBForecast { date: "May 03 2024".to_string(), s1: 35, s2: 35, s3: 5, s4: 5, s5: 0, },
+            SRSRBForecast { date: "May 01 2024".to_string(), s1: 55, s2: 55, s3: 10, s4: 10, s5: 10, },
+            SRSRBForecast { date: "May 02 2024".to_string(), s1: 45, s2: 45, s3: 10, s4: 10, s5: 10, },
+            SRSRBForecast { date: "May 03 2024".to_string(), s1: 35, s2: 35, s3: 5, s4: 5, s5: 5, },
         ];
         let (_, data) = parse_rb_forecast(SW_FORECAST_DATA1).finish().unwrap();
         for i in 0..rb_forecast1.len() {
@@ -418,7 +670,7 @@ NOAA Kp index breakdown May 01-May 03 2024
             KPForecast { date: "May 01 2024".to_string(), hour: 15, value: 2.67 },
             KPForecast { date: "May 01 2024".to_string(), hour: 18, value: 2.33 },
             KPForecast { date: "May 01 2024".to_string(), hour: 21, value: 3.00 },
-            KPForecast { date: "May 01 2024".to_string(), hour: 0, value: 3.33 },
+            KPForecast { date: "May 01 2024".to_string(), hour: 24, value: 3.33 },
 
             KPForecast { date: "May 02 2024".to_string(), hour: 3, value: 3.67 },
             KPForecast { date: "May 02 2024".to_string(), hour: 6, value: 4.0 },
@@ -427,7 +679,7 @@ NOAA Kp index breakdown May 01-May 03 2024
             KPForecast { date: "May 02 2024".to_string(), hour: 15, value: 6.0 },
             KPForecast { date: "May 02 2024".to_string(), hour: 18, value: 2.67 },
             KPForecast { date: "May 02 2024".to_string(), hour: 21, value: 3.67 },
-            KPForecast { date: "May 02 2024".to_string(), hour: 0, value: 3.67 },
+            KPForecast { date: "May 02 2024".to_string(), hour: 24, value: 3.67 },
         ];
         let (_, kp_data) = parse_kp_forecast(incomplete_text).finish().unwrap();
         for i in 0..kp_forecast.len() {
@@ -457,6 +709,69 @@ NOAA Kp index breakdown May 01-May 03 2024
         );
     }
 
+    #[test]
+    fn test_parse_kp_forecast_chronological_order() {
+        let text = "
+NOAA Kp index breakdown May 31-Jun 02 2024
+
+             May 31       Jun 01       Jun 02
+00-03UT       1.00         2.00         3.00
+03-06UT       1.33         2.33         3.33
+21-00UT       1.67         2.67         3.67
+";
+        let (_, kp_data) = parse_kp_forecast(text).finish().unwrap();
+        let order: Vec<_> = kp_data.iter().map(|kp| (kp.date.as_str(), kp.hour)).collect();
+        assert_eq!(order, [("May 31 2024", 3), ("May 31 2024", 6), ("May 31 2024", 24),
+                           ("Jun 01 2024", 3), ("Jun 01 2024", 6), ("Jun 01 2024", 24),
+                           ("Jun 02 2024", 3), ("Jun 02 2024", 6), ("Jun 02 2024", 24)]);
+        assert_eq!(kp_data[2].value, 1.67);
+    }
+
+    #[test]
+    fn test_parse_rb_forecast_new_year() {
+        let text = "
+B.  NOAA Solar Radiation Activity Observation and Forecast
+
+Radio Blackout Forecast for Dec 30-Jan 01 2025
+
+              Dec 30        Dec 31        Jan 01
+R1-R2           10%           20%           30%
+R3 or greater    1%            2%            3%
+";
+        let (_, rb_data) = parse_rb_forecast(text).finish().unwrap();
+        let dates: Vec<_> = rb_data.iter().map(|rb| (rb.date.as_str(), rb.s1)).collect();
+        assert_eq!(dates, [("Dec 30 2024", 10), ("Dec 31 2024", 20), ("Jan 01 2025", 30)]);
+    }
+
+    #[test]
+    fn test_parse_malformed_numbers() {
+        assert!(parse_hours_interval("300-03UT").finish().is_err());
+        assert!(parse_hours_interval("99999999999-03UT").finish().is_err());
+        assert_eq!(parse_hours_interval("21-00UT").finish(), Ok(("", (21, 0))));
+        assert!(parse_prcnt_val("256%").finish().is_err());
+        assert!(parse_solar_rb_storms("S9 or greater", 'S').finish().is_err());
+        assert!(parse_solar_rb_storms("R0-R2", 'R').finish().is_err());
+        assert_eq!(parse_solar_rb_storms("R1-R2", 'R').finish(), Ok(("", (1, 2))));
+    }
+
+    #[test]
+    fn test_parse_storms_or_greater() {
+        assert_eq!(parse_solar_rb_storms("S1 or greater", 'S').finish(), Ok(("", (1, 5))));
+        assert_eq!(parse_solar_rb_storms("R3 or greater", 'R').finish(), Ok(("", (3, 5))));
+        assert_eq!(parse_solar_rb_storms("S5 or greater", 'S').finish(), Ok(("", (5, 5))));
+    }
+
+    #[test]
+    fn test_parse_kp_fct_fail_malformed_hours() {
+        let wrong_text:&str = "
+NOAA Kp index breakdown May 01-May 03 2024
+
+             May 01       May 02       May 03
+300-303UT     4.67 (G1)    3.67         3.67
+";
+        assert!(parse_kp_forecast(wrong_text).finish().is_err());
+    }
+
     #[test]
     fn test_parse_srs_fct_fail_result() {
         let wrong_text:&str = "
@@ -486,4 +801,23 @@ S1 or greater    5%      5o%      5%
                    )
         );
     }
+
+    // Every captured forecast must be parsed completely, catches breakage from NOAA wording changes
+    #[test]
+    fn test_parse_captured_forecasts() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/tests/fixtures/forecasts");
+        let mut paths: Vec<_> = std::fs::read_dir(dir).unwrap()
+                                                      .map(|entry| entry.unwrap().path())
+                                                      .filter(|path| path.extension().is_some_and(|ext| ext == "txt"))
+                                                      .collect();
+        paths.sort();
+        assert!(!paths.is_empty(), "no captured forecasts in {dir}");
+        for path in paths {
+            let text = std::fs::read_to_string(&path).unwrap();
+            let forecast = parse_sw_forecast(&text).unwrap_or_else(|e| panic!("{}: {e}", path.display()));
+            assert!(!forecast.kp.is_empty(), "{}: no kp forecast", path.display());
+            assert!(!forecast.srs.is_empty(), "{}: no solar radiation storm forecast", path.display());
+            assert!(!forecast.rb.is_empty(), "{}: no radio blackout forecast", path.display());
+        }
+    }
 }
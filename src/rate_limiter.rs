@@ -0,0 +1,81 @@
+use std::time::{Duration, Instant};
+
+
+// Token bucket shared by all sources, allows burst of `per_minute` requests and then one request per
+// 1/`per_minute` of minute. Tokens are reserved in advance, so concurrent callers wait in turn.
+#[derive(Debug, Clone)]
+pub struct TRateLimiter {
+    capacity: f64,
+    // time to refill single token, None - limiter disabled
+    refill: Option<Duration>,
+    // may be negative when tokens are reserved by waiting callers
+    tokens: f64,
+    last_refill: Instant,
+}
+
+impl TRateLimiter {
+    // zero rate disables limiter
+    pub fn new(per_minute: u32, now: Instant) -> Self {
+        let refill = (per_minute > 0).then(|| Duration::from_secs(60) / per_minute);
+        Self { capacity: per_minute.into(), refill, tokens: per_minute.into(), last_refill: now }
+    }
+
+    // Takes token and returns how long caller must wait before request
+    pub fn reserve(&mut self, now: Instant) -> Duration {
+        let Some(refill) = self.refill else {
+            return Duration::ZERO;
+        };
+        let elapsed = now.saturating_duration_since(self.last_refill);
+        self.tokens = (self.tokens + elapsed.as_secs_f64() / refill.as_secs_f64()).min(self.capacity);
+        self.last_refill = now;
+        self.tokens -= 1.0;
+        if self.tokens >= 0.0 {
+            Duration::ZERO
+        } else {
+            refill.mul_f64(-self.tokens)
+        }
+    }
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_limiter_burst_then_wait() {
+        let now = Instant::now();
+        let mut limiter = TRateLimiter::new(3, now);
+        for _ in 0..3 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+        // every next caller waits one refill longer
+        assert_eq!(limiter.reserve(now), Duration::from_secs(20));
+        assert_eq!(limiter.reserve(now), Duration::from_secs(40));
+    }
+
+    #[test]
+    fn test_limiter_refill() {
+        let now = Instant::now();
+        let mut limiter = TRateLimiter::new(2, now);
+        limiter.reserve(now);
+        limiter.reserve(now);
+        assert_eq!(limiter.reserve(now + Duration::from_secs(30)), Duration::ZERO);
+        // bucket doesn't grow over capacity
+        let later = now + Duration::from_secs(3600);
+        for _ in 0..2 {
+            assert_eq!(limiter.reserve(later), Duration::ZERO);
+        }
+        assert_eq!(limiter.reserve(later), Duration::from_secs(30));
+    }
+
+    #[test]
+    fn test_limiter_disabled() {
+        let now = Instant::now();
+        let mut limiter = TRateLimiter::new(0, now);
+        for _ in 0..100 {
+            assert_eq!(limiter.reserve(now), Duration::ZERO);
+        }
+    }
+}
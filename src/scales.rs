@@ -0,0 +1,147 @@
+// NOAA space weather scales and classes, see https://www.swpc.noaa.gov/noaa-scales-explanation
+// Scale functions return level 1..5, 0 means below scale.
+
+// Geomagnetic storms level G1..G5 from Kp. Kp is reported in thirds (e.g. 4.67 is "5-"),
+// NOAA counts 5- as G1 and 9- as G4, so thresholds are taken between neighbouring thirds.
+pub fn kp_to_g_scale(kp: f32) -> u8 {
+    const THRESHOLDS: [f32; 5] = [4.5, 5.5, 6.5, 7.5, 8.83];
+    level(f64::from(kp), &THRESHOLDS.map(f64::from))
+}
+
+// Solar radiation storms level S1..S5 from >=10 MeV integral proton flux (pfu)
+pub fn proton_flux_to_s_scale(flux_gt10mev: f32) -> u8 {
+    level(f64::from(flux_gt10mev), &[1e1, 1e2, 1e3, 1e4, 1e5])
+}
+
+// Risk to satellites from >=2 MeV integral electron flux (pfu), NOAA issues alert at 1000 pfu.
+// Electron flux has no NOAA scale, so risk is reported by name.
+pub fn electron_flux_risk(flux_gt2mev: f32) -> &'static str {
+    match flux_gt2mev {
+        flux if flux >= 1e4 => "high",
+        flux if flux >= 1e3 => "moderate",
+        _ => "low",
+    }
+}
+
+// Radio blackouts level R1..R5 from 0.1-0.8 nm X-ray flux (W/m2), i.e. flares M1, M5, X1, X10, X20
+pub fn xray_flux_to_r_scale(xray_flux: f64) -> u8 {
+    level(xray_flux, &[1e-5, 5e-5, 1e-4, 1e-3, 2e-3])
+}
+
+// Flare class from 0.1-0.8 nm X-ray flux (W/m2), e.g. 2.3e-5 is "M2.3"
+pub fn xray_flux_to_flare_class(xray_flux: f64) -> String {
+    const CLASSES: [(char, f64); 5] = [('X', 1e-4), ('M', 1e-5), ('C', 1e-6), ('B', 1e-7), ('A', 1e-8)];
+    let (letter, base) = CLASSES.into_iter().find(|(_, base)| xray_flux >= *base).unwrap_or(CLASSES[4]);
+    format!("{letter}{:.1}", xray_flux / base)
+}
+
+pub fn scale_name(scale: char, level: u8) -> Option<String> {
+    (level > 0).then(|| format!("{scale}{level}"))
+}
+
+// Overall space weather condition from the highest scale level across G/S/R scales
+#[derive(Debug, Clone, Copy, PartialEq, Eq, PartialOrd, Ord)]
+pub enum Severity {
+    Quiet,
+    // minor storm or blackout, level 1
+    Unsettled,
+    // levels 2..3
+    Storm,
+    // levels 4..5
+    Severe,
+}
+
+impl Severity {
+    pub fn from_level(level: u8) -> Self {
+        match level {
+            0 => Severity::Quiet,
+            1 => Severity::Unsettled,
+            2..=3 => Severity::Storm,
+            _ => Severity::Severe,
+        }
+    }
+
+    pub fn name(&self) -> &'static str {
+        match self {
+            Severity::Quiet => "Quiet",
+            Severity::Unsettled => "Unsettled",
+            Severity::Storm => "Storm",
+            Severity::Severe => "Severe",
+        }
+    }
+}
+
+fn level(value: f64, thresholds: &[f64; 5]) -> u8 {
+    thresholds.iter().filter(|threshold| value >= **threshold).count() as u8
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_kp_to_g_scale() {
+        // values and scales as printed in NOAA 3-day forecast
+        assert_eq!(kp_to_g_scale(3.67), 0);
+        assert_eq!(kp_to_g_scale(4.33), 0);
+        assert_eq!(kp_to_g_scale(4.67), 1);
+        assert_eq!(kp_to_g_scale(6.00), 2);
+        assert_eq!(kp_to_g_scale(7.00), 3);
+        assert_eq!(kp_to_g_scale(8.67), 4);
+        assert_eq!(kp_to_g_scale(9.00), 5);
+    }
+
+    #[test]
+    fn test_proton_flux_to_s_scale() {
+        assert_eq!(proton_flux_to_s_scale(0.35), 0);
+        assert_eq!(proton_flux_to_s_scale(10.0), 1);
+        assert_eq!(proton_flux_to_s_scale(99.0), 1);
+        assert_eq!(proton_flux_to_s_scale(100.0), 2);
+        assert_eq!(proton_flux_to_s_scale(2500.0), 3);
+        assert_eq!(proton_flux_to_s_scale(10000.0), 4);
+        assert_eq!(proton_flux_to_s_scale(100000.0), 5);
+    }
+
+    #[test]
+    fn test_electron_flux_risk() {
+        assert_eq!(electron_flux_risk(812.4), "low");
+        assert_eq!(electron_flux_risk(1000.0), "moderate");
+        assert_eq!(electron_flux_risk(9999.0), "moderate");
+        assert_eq!(electron_flux_risk(25000.0), "high");
+    }
+
+    #[test]
+    fn test_xray_flux_to_r_scale() {
+        assert_eq!(xray_flux_to_r_scale(9e-6), 0);
+        assert_eq!(xray_flux_to_r_scale(1e-5), 1);
+        assert_eq!(xray_flux_to_r_scale(5e-5), 2);
+        assert_eq!(xray_flux_to_r_scale(2e-4), 3);
+        assert_eq!(xray_flux_to_r_scale(1e-3), 4);
+        assert_eq!(xray_flux_to_r_scale(3e-3), 5);
+    }
+
+    #[test]
+    fn test_xray_flux_to_flare_class() {
+        assert_eq!(xray_flux_to_flare_class(2.3e-5), "M2.3");
+        assert_eq!(xray_flux_to_flare_class(1.0e-4), "X1.0");
+        assert_eq!(xray_flux_to_flare_class(2.8e-3), "X28.0");
+        assert_eq!(xray_flux_to_flare_class(4.5e-7), "B4.5");
+        assert_eq!(xray_flux_to_flare_class(5.0e-9), "A0.5");
+    }
+
+    #[test]
+    fn test_severity_from_level() {
+        assert_eq!(Severity::from_level(0), Severity::Quiet);
+        assert_eq!(Severity::from_level(1), Severity::Unsettled);
+        assert_eq!(Severity::from_level(3), Severity::Storm);
+        assert_eq!(Severity::from_level(5).name(), "Severe");
+    }
+
+    #[test]
+    fn test_scale_name() {
+        assert_eq!(scale_name('G', 0), None);
+        assert_eq!(scale_name('G', 2), Some("G2".to_string()));
+    }
+}
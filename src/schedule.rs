@@ -0,0 +1,126 @@
+use chrono::{DateTime, Utc};
+use std::fmt;
+use std::str::FromStr;
+
+
+// Cron expression "minute hour day-of-month month day-of-week" evaluated in UTC, e.g. "2,32 * * * *".
+// Fields support `*`, lists, ranges and steps: "*/15", "0-30/10", "1,15". Parsed by cron crate with zero second,
+// so day of week is 1-7 from Sunday or name like "Mon", day of month and day of week must both match.
+#[derive(Debug, Clone, PartialEq)]
+pub struct TCronSchedule {
+    expression: String,
+    schedule: cron::Schedule,
+}
+
+impl FromStr for TCronSchedule {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        let expression = s.split_whitespace().collect::<Vec<_>>().join(" ");
+        if expression.split(' ').count() != 5 {
+            return Err(format!("cron expression '{s}' must have 5 fields: minute hour day-of-month month \
+                                day-of-week"));
+        }
+        let schedule = format!("0 {expression}").parse::<cron::Schedule>()
+            .map_err(|e| format!("wrong cron expression '{s}': {e}"))?;
+        Ok(Self { expression, schedule })
+    }
+}
+
+impl fmt::Display for TCronSchedule {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str(&self.expression)
+    }
+}
+
+impl TCronSchedule {
+    // First fire time strictly after `after`, None if expression never fires
+    pub fn next_after(&self, after: DateTime<Utc>) -> Option<DateTime<Utc>> {
+        self.schedule.after(&after).next()
+    }
+
+    // Waiting time until next fire
+    pub fn wait_from(&self, now: DateTime<Utc>) -> Option<std::time::Duration> {
+        (self.next_after(now)? - now).to_std().ok()
+    }
+}
+
+// Waiting time until next wall-clock boundary of interval counted from midnight UTC of Unix epoch,
+// e.g. :00, :10, :20 for 10 minutes. Zero if `now` is at boundary.
+pub fn align_delay(now: DateTime<Utc>, interval: std::time::Duration) -> std::time::Duration {
+    let interval_ms = interval.as_millis();
+    if interval_ms == 0 {
+        return std::time::Duration::ZERO;
+    }
+    let elapsed_ms = u128::try_from(now.timestamp_millis()).unwrap_or_default() % interval_ms;
+    let delay_ms = (interval_ms - elapsed_ms) % interval_ms;
+    std::time::Duration::from_millis(u64::try_from(delay_ms).unwrap_or(u64::MAX))
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use chrono::TimeZone;
+
+    fn utc(year: i32, month: u32, day: u32, hour: u32, minute: u32) -> DateTime<Utc> {
+        Utc.with_ymd_and_hms(year, month, day, hour, minute, 0).unwrap()
+    }
+
+    #[test]
+    fn test_next_after_minutes() {
+        let schedule: TCronSchedule = "2,32 * * * *".parse().unwrap();
+        assert_eq!(schedule.next_after(utc(2024, 5, 1, 10, 0)), Some(utc(2024, 5, 1, 10, 2)));
+        assert_eq!(schedule.next_after(utc(2024, 5, 1, 10, 2)), Some(utc(2024, 5, 1, 10, 32)));
+        assert_eq!(schedule.next_after(utc(2024, 5, 1, 23, 45)), Some(utc(2024, 5, 2, 0, 2)));
+        let every_quarter: TCronSchedule = "*/15 * * * *".parse().unwrap();
+        assert_eq!(every_quarter.next_after(utc(2024, 5, 1, 10, 50)), Some(utc(2024, 5, 1, 11, 0)));
+    }
+
+    #[test]
+    fn test_next_after_days() {
+        // 00:30 on the first day of month
+        let schedule: TCronSchedule = "30 0 1 * *".parse().unwrap();
+        assert_eq!(schedule.next_after(utc(2024, 5, 1, 0, 30)), Some(utc(2024, 6, 1, 0, 30)));
+        // 2024-05-04 is Saturday, Sunday is 1
+        let sundays: TCronSchedule = "0 12 * * Sun".parse().unwrap();
+        assert_eq!(sundays.next_after(utc(2024, 5, 1, 0, 0)), Some(utc(2024, 5, 5, 12, 0)));
+        assert_eq!("0 12 * * 1".parse::<TCronSchedule>().unwrap().next_after(utc(2024, 5, 1, 0, 0)),
+                   Some(utc(2024, 5, 5, 12, 0)));
+        // both day of month and day of week, 2024-05-10 is Friday
+        let both: TCronSchedule = "0 0 */2 * Fri".parse().unwrap();
+        assert_eq!(both.next_after(utc(2024, 5, 6, 0, 0)), Some(utc(2024, 5, 17, 0, 0)));
+        let never: TCronSchedule = "0 0 30 2 *".parse().unwrap();
+        assert_eq!(never.next_after(utc(2024, 5, 1, 0, 0)), None);
+    }
+
+    #[test]
+    fn test_wait_from() {
+        let schedule: TCronSchedule = "2 * * * *".parse().unwrap();
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 1, 30).unwrap();
+        assert_eq!(schedule.wait_from(now), Some(std::time::Duration::from_secs(30)));
+        assert_eq!(schedule.to_string(), "2 * * * *");
+    }
+
+    #[test]
+    fn test_align_delay() {
+        let now = Utc.with_ymd_and_hms(2024, 5, 1, 10, 3, 30).unwrap();
+        assert_eq!(align_delay(now, std::time::Duration::from_secs(600)), std::time::Duration::from_secs(390));
+        // 6h boundaries are 00:00, 06:00, 12:00 and 18:00 UTC
+        assert_eq!(align_delay(now, std::time::Duration::from_secs(6 * 3600)),
+                   std::time::Duration::from_secs(3600 + 56 * 60 + 30));
+        assert_eq!(align_delay(utc(2024, 5, 1, 10, 0), std::time::Duration::from_secs(600)),
+                   std::time::Duration::ZERO);
+        assert_eq!(align_delay(now, std::time::Duration::ZERO), std::time::Duration::ZERO);
+    }
+
+    #[test]
+    fn test_parse_errors() {
+        assert!("* * * *".parse::<TCronSchedule>().is_err());
+        assert!("60 * * * *".parse::<TCronSchedule>().is_err());
+        assert!("*/0 * * * *".parse::<TCronSchedule>().is_err());
+        assert!("30-10 * * * *".parse::<TCronSchedule>().is_err());
+        assert!("* * * 13 *".parse::<TCronSchedule>().is_err());
+    }
+}
@@ -0,0 +1,93 @@
+use std::collections::HashMap;
+use std::fmt;
+use std::str::FromStr;
+
+
+// Config value that must not appear in logs, e.g. password, printed config shows it masked
+#[derive(Clone, PartialEq)]
+pub struct TSecret(pub String);
+
+impl FromStr for TSecret {
+    type Err = String;
+
+    fn from_str(s: &str) -> Result<Self, Self::Err> {
+        Ok(TSecret(s.to_string()))
+    }
+}
+
+impl fmt::Debug for TSecret {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        f.write_str("\"***\"")
+    }
+}
+
+// Config values of `TSecret` type, only they can be read from files
+pub const SECRET_NAMES: [&str; 2] = ["MQTT_BROKER_PASSWORD", "INFLUXDB_TOKEN"];
+
+// Resolves `<NAME>_FILE` env vars of secrets (Docker/Kubernetes secrets convention): value of <NAME> is read
+// from file at given path, e.g. MQTT_BROKER_PASSWORD_FILE=/run/secrets/mqtt_password. Trailing line ending
+// is trimmed. Other `*_FILE` vars, e.g. SSL_CERT_FILE, are left as they are.
+pub fn resolve_secret_files(vars: impl Iterator<Item = (String, String)>,
+                            read: impl Fn(&str) -> std::io::Result<String>) -> Result<HashMap<String, String>, String> {
+    let mut env: HashMap<String, String> = vars.collect();
+    let files: Vec<(String, String)> = env.iter()
+        .filter_map(|(name, path)| Some((name.strip_suffix("_FILE")?.to_string(), path.clone())))
+        .filter(|(name, _)| SECRET_NAMES.contains(&name.as_str()))
+        .collect();
+    for (name, path) in files {
+        if env.contains_key(&name) {
+            return Err(format!("both {name} and {name}_FILE are set"));
+        }
+        let value = read(&path).map_err(|e| format!("can't read {name}_FILE '{path}': {e}"))?;
+        env.insert(name, value.trim_end_matches(['\n', '\r']).to_string());
+    }
+    Ok(env)
+}
+
+// Tests
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn read_fake(path: &str) -> std::io::Result<String> {
+        match path {
+            "/run/secrets/mqtt_password" => Ok("s3cret\n".to_string()),
+            _ => Err(std::io::Error::new(std::io::ErrorKind::NotFound, "not found")),
+        }
+    }
+
+    fn vars(vars: &[(&str, &str)]) -> impl Iterator<Item = (String, String)> {
+        vars.iter().map(|(name, value)| (name.to_string(), value.to_string())).collect::<Vec<_>>().into_iter()
+    }
+
+    #[test]
+    fn test_resolve_secret_files() {
+        let env = resolve_secret_files(vars(&[("MQTT_BROKER_PASSWORD_FILE", "/run/secrets/mqtt_password"),
+                                              ("MQTT_BROKER_HOST", "broker")]), read_fake).unwrap();
+        assert_eq!(env.get("MQTT_BROKER_PASSWORD").map(String::as_str), Some("s3cret"));
+        assert_eq!(env.get("MQTT_BROKER_HOST").map(String::as_str), Some("broker"));
+    }
+
+    #[test]
+    fn test_resolve_secret_files_other_vars() {
+        let env = resolve_secret_files(vars(&[("SSL_CERT_FILE", "/etc/ssl/certs/ca.pem"), ("_FILE", "/missing")]),
+                                       read_fake).unwrap();
+        assert_eq!(env.get("SSL_CERT_FILE").map(String::as_str), Some("/etc/ssl/certs/ca.pem"));
+        assert_eq!(env.get("SSL_CERT"), None);
+    }
+
+    #[test]
+    fn test_resolve_secret_files_errors() {
+        let result = resolve_secret_files(vars(&[("INFLUXDB_TOKEN_FILE", "/missing")]), read_fake);
+        assert_eq!(result.unwrap_err(), "can't read INFLUXDB_TOKEN_FILE '/missing': not found");
+        let result = resolve_secret_files(vars(&[("MQTT_BROKER_PASSWORD_FILE", "/run/secrets/mqtt_password"),
+                                                 ("MQTT_BROKER_PASSWORD", "plain")]), read_fake);
+        assert_eq!(result.unwrap_err(), "both MQTT_BROKER_PASSWORD and MQTT_BROKER_PASSWORD_FILE are set");
+    }
+
+    #[test]
+    fn test_secret_debug_masked() {
+        assert_eq!(format!("{:?}", Some(TSecret("s3cret".to_string()))), "Some(\"***\")");
+    }
+}